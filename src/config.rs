@@ -0,0 +1,215 @@
+//! The optional TOML config file, `~/.config/clashui/config.toml` unless
+//! another path is given with `--config`. Command line arguments win over
+//! the file, which wins over the built in defaults.
+//!
+//! ```toml
+//! base_url = "http://192.168.1.1:9090"
+//! secret = "hunter2"
+//! connect_retry_secs = 60
+//! log_lines = 5000
+//! user_agent = "clashui"
+//! timeout_secs = 10
+//! accessible = true
+//! pulse = false
+//! hints = false
+//! group_order = ["Proxy", "Auto"]
+//! latency_thresholds = [150, 400]
+//! test_urls = [
+//!     "http://www.gstatic.com/generate_204",
+//!     "http://cp.cloudflare.com/generate_204",
+//! ]
+//! test_strategy = "median"
+//! auto_test = true
+//! insecure = false
+//!
+//! [theme]
+//! highlight = "#d0d0ff"
+//! active = "red"
+//!
+//! [groups.auto]
+//! sort = "latency"
+//!
+//! [groups.fallback]
+//! sort = "original"
+//!
+//! [groups.manual]
+//! sort = "pinned"
+//! order = ["Tokyo-01", "Osaka-02"]
+//!
+//! # replaces the default sidebar sections
+//! [[menu]]
+//! title = "Proxies"
+//! routes = ["proxies", "providers", "rules"]
+//!
+//! [[menu]]
+//! title = "Debug"
+//! routes = ["general", "connections", "logs"]
+//! ```
+
+use crate::{store, theme::Theme, Route};
+use serde::Deserialize;
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt, fs, io,
+	path::{Path, PathBuf},
+};
+
+#[derive(Default, Deserialize)]
+pub struct ConfigFile {
+	// the controller, http://localhost:9090 when unset; may include the
+	// path it is served under, like https://example.com/clash-api
+	pub base_url: Option<String>,
+	// the controller's `secret`, after --token and CLASH_SECRET
+	pub secret: Option<String>,
+	// how long to keep retrying the first connect of the log stream,
+	// 60 seconds when unset
+	pub connect_retry_secs: Option<u64>,
+	// log entries kept for the Logs page, 1000 when unset
+	pub log_lines: Option<usize>,
+	// `clashui/<version>` when unset
+	pub user_agent: Option<String>,
+	// how long requests wait for an answer, 5 seconds when unset and
+	// forever when 0; same as --timeout-secs
+	pub timeout_secs: Option<u64>,
+	// symbols next to colors, same as --accessible
+	#[serde(default)]
+	pub accessible: bool,
+	// the dot blinking on each tick in the top right corner, on when
+	// unset
+	pub pulse: Option<bool>,
+	// the keys of each pane on its first visit, on when unset
+	pub hints: Option<bool>,
+	// delay test endpoints, gstatic's generate_204 when empty
+	#[serde(default)]
+	pub test_urls: Vec<String>,
+	// how `test_urls` are used, round robin when unset
+	pub test_strategy: Option<TestStrategy>,
+	// delay tests a group's nodes when its tab stays selected for a
+	// moment, unless they were tested in the last minute; same as
+	// --auto-test
+	#[serde(default)]
+	pub auto_test: bool,
+	// accepts any certificate from an https controller, same as
+	// --insecure
+	#[serde(default)]
+	pub insecure: bool,
+	// delays below the first are green, below the second yellow, the
+	// rest red; [200, 500] when unset
+	pub latency_thresholds: Option<[u16; 2]>,
+	// tabs shown first in this order, the rest sorted by name
+	#[serde(default)]
+	pub group_order: Vec<String>,
+	// colors, see the theme module
+	#[serde(default)]
+	pub theme: Theme,
+	// per group name
+	#[serde(default)]
+	pub groups: HashMap<String, GroupConfig>,
+	// sidebar sections, the built in ones when empty
+	#[serde(default)]
+	pub menu: Vec<MenuSection>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct MenuSection {
+	pub title: String,
+	pub routes: Vec<Route>,
+}
+
+#[derive(Default, Deserialize)]
+pub struct GroupConfig {
+	pub sort: Option<SortOrder>,
+	// nodes listed first when sorting as `pinned`
+	#[serde(default)]
+	pub order: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+	Alphabetical,
+	Latency,
+	// as the controller lists them, usually the order of the config
+	Original,
+	Pinned,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TestStrategy {
+	// one URL per test, taking turns
+	#[default]
+	RoundRobin,
+	// every URL, keeping the fastest
+	Best,
+	// every URL, keeping the median of those that answered
+	Median,
+}
+
+impl SortOrder {
+	pub fn next(self) -> Self {
+		match self {
+			SortOrder::Alphabetical => SortOrder::Latency,
+			SortOrder::Latency => SortOrder::Original,
+			SortOrder::Original => SortOrder::Pinned,
+			SortOrder::Pinned => SortOrder::Alphabetical,
+		}
+	}
+}
+
+impl fmt::Display for SortOrder {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			SortOrder::Alphabetical => "alphabetical",
+			SortOrder::Latency => "latency",
+			SortOrder::Original => "original",
+			SortOrder::Pinned => "pinned",
+		};
+		f.write_str(name)
+	}
+}
+
+impl ConfigFile {
+	// only an explicitly given file has to exist
+	pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+		let (path, explicit) = match path {
+			Some(path) => (path.to_owned(), true),
+			_ => match default_path() {
+				Some(path) => (path, false),
+				_ => return Ok(Self::default()),
+			},
+		};
+
+		let text = match fs::read_to_string(&path) {
+			Ok(text) => text,
+			Err(err) if err.kind() == io::ErrorKind::NotFound
+				&& !explicit =>
+			{
+				return Ok(Self::default())
+			}
+			Err(err) => {
+				return Err(format!(
+					"{}: {}",
+					path.display(),
+					err
+				)
+				.into())
+			}
+		};
+		toml::from_str(&text).map_err(|err| {
+			format!(
+				"{}: {}\nFix the file or point --config at another one. \
+				 Command line arguments override the file, which \
+				 overrides the defaults.",
+				path.display(),
+				err
+			)
+			.into()
+		})
+	}
+}
+
+fn default_path() -> Option<PathBuf> {
+	store::config_dir().map(|dir| dir.join("config.toml"))
+}
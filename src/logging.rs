@@ -0,0 +1,33 @@
+//! clashui's own log, for finding out why a request failed or a key did
+//! nothing. The terminal belongs to the UI, so it is only written to the
+//! file given with `--log-file`, filtered by `RUST_LOG` as env_logger reads
+//! it (`info` when unset). The HTTP libraries log under their own targets,
+//! `reqwest` and `hyper`.
+
+use env_logger::{Env, Target, WriteStyle};
+use std::{fs::File, panic, path::Path};
+
+// Appends to `path` from here on. A panic is logged and the log flushed
+// before the previous hook prints it.
+pub fn init(path: &Path) -> Result<(), String> {
+	let file = File::options()
+		.create(true)
+		.append(true)
+		.open(path)
+		.map_err(|err| {
+			format!("Could not open {}: {}", path.display(), err)
+		})?;
+	env_logger::Builder::from_env(Env::default().default_filter_or("info"))
+		.target(Target::Pipe(Box::new(file)))
+		.write_style(WriteStyle::Never)
+		.try_init()
+		.map_err(|err| err.to_string())?;
+
+	let previous = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		log::error!("{}", info);
+		log::logger().flush();
+		previous(info);
+	}));
+	Ok(())
+}
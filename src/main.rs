@@ -1,201 +1,1281 @@
-use clap::Parser;
+mod config;
+mod logging;
+mod matcher;
+mod store;
+mod theme;
+
+use clap::{Parser, ValueEnum};
+use config::{ConfigFile, MenuSection, SortOrder, TestStrategy};
 use crossterm::{
-	event,
-	event::{Event, KeyCode},
+	cursor, event,
+	event::{
+		DisableMouseCapture, EnableMouseCapture, Event, KeyCode,
+		KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+		MouseEventKind,
+	},
 	execute,
 	terminal::{
 		disable_raw_mode, enable_raw_mode, EnterAlternateScreen,
 		LeaveAlternateScreen,
 	},
 };
+use futures_util::{stream, StreamExt, TryStreamExt};
 use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
-use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use reqwest::{
+	header::{HeaderMap, HeaderValue, AUTHORIZATION},
+	Client, RequestBuilder, Response, StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{
-	collections::HashMap,
+	cmp,
+	collections::{HashMap, HashSet, VecDeque},
+	env,
 	error::Error,
-	fmt, io,
-	time::{Duration, Instant},
+	fmt,
+	fs::File,
+	future::Future,
+	io::{self, BufReader, BufWriter, Write},
+	panic,
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		mpsc::{self, Receiver, Sender},
+		Arc,
+	},
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use store::{Profile, Store};
+use theme::Theme;
+use tokio::{
+	io::{AsyncBufRead, AsyncBufReadExt},
+	time,
+};
+use tokio_util::io::{StreamReader, SyncIoBridge};
 use tui::{
 	backend::{Backend, CrosstermBackend},
-	layout::{Constraint, Direction, Layout, Rect},
+	layout::{Constraint, Direction, Layout, Margin, Rect},
 	style::{Color, Modifier, Style},
 	text::{Span, Spans},
-	widgets::{Block, Borders, List, ListItem, Tabs},
+	widgets::{
+		Block, Clear, List, ListItem, ListState, Paragraph, Row,
+		Sparkline, Table, TableState, Tabs, Wrap,
+	},
 	Frame, Terminal,
 };
 
 #[derive(Parser)]
 struct Cli {
+	/// The controller, http://localhost:9090 by default; may include the
+	/// path it is served under behind a reverse proxy
 	base_url: Option<String>,
-	// TODO: token: Option<String>,
+	/// The controller's `secret`, sent as a bearer token
+	#[arg(long, env = "CLASH_SECRET", hide_env_values = true)]
+	token: Option<String>,
+	/// Config file, defaults to ~/.config/clashui/config.toml
+	#[arg(long)]
+	config: Option<PathBuf>,
+	/// User-Agent sent to the controller, overrides the config file
+	#[arg(long)]
+	user_agent: Option<String>,
+	/// Only show the mode selector, Esc or q quits
+	#[arg(long)]
+	mode_only: bool,
+	/// Mark the cursor, active items and errors with symbols too
+	#[arg(long)]
+	accessible: bool,
+	/// Also append the live log stream to this file
+	#[arg(long)]
+	log_tail: Option<PathBuf>,
+	/// Append clashui's own log, the requests it sends and what keys do,
+	/// to this file; RUST_LOG sets the level, info by default
+	#[arg(long)]
+	log_file: Option<PathBuf>,
+	/// Print the mode and selections to stdout after quitting
+	#[arg(long)]
+	print_summary_on_exit: bool,
+	/// Seconds between refreshes of the Connections page, 0 to only load
+	/// pages when they are opened or reloaded with r. Rules also refresh
+	/// every 10 seconds unless this is 0, the other pages never do.
+	#[arg(long, default_value_t = 1)]
+	refresh_secs: u64,
+	/// Start without checking that the controller answers, for starting
+	/// the UI before Clash
+	#[arg(long)]
+	no_preflight: bool,
+	/// Delay test a group when its tab stays selected for a second,
+	/// unless it was tested in the last minute
+	#[arg(long)]
+	auto_test: bool,
+	/// Page to open, instead of the one open when last quitting
+	#[arg(long, value_enum)]
+	start_page: Option<Route>,
+	/// Accept any TLS certificate from an https controller, for
+	/// self-signed ones
+	#[arg(long)]
+	insecure: bool,
+	/// Seconds to wait for the controller to answer a request, 0 to wait
+	/// forever; delay tests wait for their own timeout instead
+	#[arg(long)]
+	timeout_secs: Option<u64>,
 }
 
-#[derive(PartialEq, Debug)]
+const USER_AGENT: &str = concat!("clashui/", env!("CARGO_PKG_VERSION"));
+// how long requests wait for an answer when neither --timeout-secs nor the
+// config file say
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+// for requests the controller only answers once its own work is done, like
+// testing every node of a provider or downloading a subscription
+const SLOW_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(
+	Clone,
+	Copy,
+	PartialEq,
+	Eq,
+	Hash,
+	Debug,
+	Deserialize,
+	Serialize,
+	ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
 enum Route {
 	General,
 	Proxies,
+	Providers,
 	Rules,
 	Connections,
 	Logs,
 }
 
+impl Route {
+	// the pane `l` focuses
+	fn pane(self) -> Pane {
+		match self {
+			Route::General => Pane::General,
+			Route::Proxies => Pane::Proxies,
+			Route::Providers => Pane::Providers,
+			Route::Rules => Pane::Rules,
+			Route::Connections => Pane::Connections,
+			Route::Logs => Pane::Logs,
+		}
+	}
+}
+
 impl fmt::Display for Route {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		fmt::Debug::fmt(self, f)
 	}
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 enum Pane {
 	Menu,
 	Proxies,
 	General,
+	Providers,
+	Rules,
+	Connections,
+	Logs,
 	// Other,
 }
 
+// the selector every connection goes through in global mode
+const GLOBAL: &str = "GLOBAL";
+
 const FRAGMENT: &AsciiSet =
 	&CONTROLS.add(b' ').add(b'"').add(b'<').add(b'>').add(b'`');
 
+// `request.send()`, logging where it went and how it was answered
+async fn send(request: RequestBuilder) -> reqwest::Result<Response> {
+	// only streamed bodies can't be cloned, none are sent
+	let target = request
+		.try_clone()
+		.and_then(|request| request.build().ok())
+		.map(|request| {
+			format!("{} {}", request.method(), request.url())
+		})
+		.unwrap_or_default();
+	let started = Instant::now();
+	let res = request.send().await;
+	match &res {
+		Ok(res) if res.status().is_success() => log::info!(
+			"{} -> {} in {:?}",
+			target,
+			res.status(),
+			started.elapsed()
+		),
+		Ok(res) => log::warn!(
+			"{} -> {} in {:?}",
+			target,
+			res.status(),
+			started.elapsed()
+		),
+		Err(err) => log::warn!("{} failed: {}", target, err),
+	}
+	res
+}
+
+// Send, the requests run in spawned tasks
+type HttpError = Box<dyn Error + Send + Sync>;
+
+// App runs requests as tasks on the tokio runtime and applies their results
+// when they come back as messages, so none of them wait on the UI thread.
+#[derive(Clone)]
 struct HttpClient {
-	// TODO: async
-	client: reqwest::blocking::Client,
+	client: Client,
+	// without the default timeout, for endpoints that never finish
+	stream_client: Client,
 	url: String,
+	user_agent: String,
+	token: Option<String>,
+	// certificates are not verified
+	insecure: bool,
+	// None waits forever
+	timeout: Option<Duration>,
 }
 
 impl HttpClient {
-	fn new(base_url: &str) -> Self {
-		Self {
-			client: Client::new(),
+	fn new(
+		base_url: &str,
+		user_agent: &str,
+		token: Option<&str>,
+		insecure: bool,
+		timeout: Option<Duration>,
+	) -> Result<Self, String> {
+		// a secret read from a file often ends in a newline
+		let token = token.map(str::trim);
+		let mut headers = HeaderMap::new();
+		if let Some(token) = token {
+			let bearer = format!("Bearer {}", token);
+			let mut value = HeaderValue::from_str(&bearer)
+				.map_err(|_| {
+					String::from(
+						"The secret has characters \
+						 not allowed in a header",
+					)
+				})?;
+			value.set_sensitive(true);
+			headers.insert(AUTHORIZATION, value);
+		}
+		let builder = || {
+			Client::builder()
+				.user_agent(user_agent)
+				.default_headers(headers.clone())
+				.danger_accept_invalid_certs(insecure)
+		};
+		let client = match timeout {
+			Some(timeout) => builder().timeout(timeout),
+			_ => builder(),
+		};
+		let failed = |err: reqwest::Error| {
+			format!("Could not set up the HTTP client: {}", err)
+		};
+		Ok(Self {
+			client: client.build().map_err(failed)?,
+			stream_client: builder().build().map_err(failed)?,
 			url: base_url.to_owned(),
+			user_agent: user_agent.to_owned(),
+			token: token.map(String::from),
+			insecure,
+			timeout,
+		})
+	}
+
+	// `path` under the controller's URL, joined by a single slash
+	fn endpoint(&self, path: &str) -> String {
+		format!(
+			"{}/{}",
+			self.url.trim_end_matches('/'),
+			path.trim_start_matches('/')
+		)
+	}
+
+	// the same settings against another controller
+	fn with_url(&self, base_url: &str) -> Result<Self, String> {
+		Self::new(
+			base_url,
+			&self.user_agent,
+			self.token.as_deref(),
+			self.insecure,
+			self.timeout,
+		)
+	}
+
+	// reqwest only says the operation timed out
+	fn describe(&self, err: &(dyn Error + 'static)) -> String {
+		let timed_out = err
+			.downcast_ref::<reqwest::Error>()
+			.is_some_and(|err| err.is_timeout());
+		match self.timeout {
+			Some(timeout) if timed_out => format!(
+				"the controller did not answer within {}s",
+				timeout.as_secs_f32()
+			),
+			_ => err.to_string(),
 		}
 	}
 
-	fn configs(&self) -> Result<Config, Box<dyn Error>> {
-		let res: Config = self
-			.client
-			.get(format!("{}{}", self.url, "/configs"))
-			.send()?
-			.json()?;
+	// the body as it downloads, for reading it while it still does
+	async fn body(
+		&self,
+		client: &Client,
+		path: &str,
+	) -> Result<impl AsyncBufRead + Send + Unpin, HttpError> {
+		let res = send(client.get(self.endpoint(path)))
+			.await?
+			.error_for_status()?;
+		let body = res.bytes_stream().map_err(io::Error::other);
+		Ok(StreamReader::new(body))
+	}
+
+	// newline delimited JSON, one entry per line
+	async fn stream(
+		&self,
+		path: &str,
+	) -> Result<impl AsyncBufRead + Send + Unpin, HttpError> {
+		self.body(&self.stream_client, path).await
+	}
+
+	// Tells an unreachable URL apart from something that answers but is
+	// not a Clash controller, e.g. the proxy port.
+	async fn version(&self) -> Result<Version, String> {
+		let res = send(self.client.get(self.endpoint("/version")))
+			.await
+			.map_err(|err| {
+				format!(
+					"Could not reach Clash controller at {}: {}",
+					self.url, err
+				)
+			})?;
+		let status = res.status();
+		let version = res.json::<Version>().await;
+		match (status.as_u16(), version) {
+			(401, _) => Err(format!(
+				"{} rejected the secret ({}), see --token",
+				self.url, status
+			)),
+			(403, _) => Err(format!(
+				"{} rejected the request ({})",
+				self.url, status
+			)),
+			(_, Ok(version)) if status.is_success() => Ok(version),
+			_ => Err(format!(
+				"{} does not look like a Clash controller ({})",
+				self.url, status
+			)),
+		}
+	}
+
+	async fn configs(&self) -> Result<Config, HttpError> {
+		let res: Config =
+			send(self.client.get(self.endpoint("/configs")))
+				.await?
+				.error_for_status()?
+				.json()
+				.await?;
 		Ok(res)
 	}
 
-	fn update_config(&self, mode: &str) -> Result<(), Box<dyn Error>> {
-		let body = HashMap::from([("mode", mode)]);
-		self.client
-			.patch(format!("{}{}", self.url, "/configs",))
+	async fn update_config(
+		&self,
+		patch: &ConfigPatch,
+	) -> Result<(), HttpError> {
+		let res = send(self
+			.client
+			.patch(self.endpoint("/configs"))
+			.json(patch))
+		.await?;
+		let status = res.status();
+		if !status.is_success() {
+			let reason = res
+				.json::<ApiError>()
+				.await
+				.map(|err| err.message)
+				.unwrap_or_else(|_| status.to_string());
+			return Err(reason.into());
+		}
+		// 204 No Content, nothing to parse
+		Ok(())
+	}
+
+	// rereads the config file at `path` on the controller's machine, the
+	// one it was started with when empty
+	async fn reload_config(&self, path: &str) -> Result<(), HttpError> {
+		let body = HashMap::from([("path", path)]);
+		let res = send(self
+			.client
+			.put(self.endpoint("/configs"))
+			.query(&[("force", "true")])
 			.json(&body)
-			.send()?
-			.json()?;
+			// the providers of the new config are downloaded first
+			.timeout(SLOW_REQUEST_TIMEOUT))
+		.await?;
+		let status = res.status();
+		if !status.is_success() {
+			let reason = res
+				.json::<ApiError>()
+				.await
+				.map(|err| err.message)
+				.unwrap_or_else(|_| status.to_string());
+			return Err(reason.into());
+		}
+		Ok(())
+	}
+
+	async fn providers(
+		&self,
+	) -> Result<HashMap<String, ProxyProvider>, HttpError> {
+		let res: ProviderList = send(self
+			.client
+			.get(self.endpoint("/providers/proxies")))
+		.await?
+		.error_for_status()?
+		.json()
+		.await?;
+		Ok(res.providers)
+	}
+
+	// Clash tests every node of the provider and answers when done
+	async fn healthcheck_provider(
+		&self,
+		name: &str,
+	) -> Result<(), HttpError> {
+		send(self
+			.client
+			.get(self.endpoint(&format!(
+				"/providers/proxies/{}/healthcheck",
+				utf8_percent_encode(name, FRAGMENT)
+			)))
+			.timeout(SLOW_REQUEST_TIMEOUT))
+		.await?
+		.error_for_status()?;
+		Ok(())
+	}
+
+	// downloads the subscription again
+	async fn update_provider(&self, name: &str) -> Result<(), HttpError> {
+		send(self
+			.client
+			.put(self.endpoint(&format!(
+				"/providers/proxies/{}",
+				utf8_percent_encode(name, FRAGMENT)
+			)))
+			.timeout(SLOW_REQUEST_TIMEOUT))
+		.await?
+		.error_for_status()?;
+		Ok(())
+	}
+
+	async fn proxies(&self) -> Result<HashMap<String, Proxy>, HttpError> {
+		let res: ProxyList =
+			send(self.client.get(self.endpoint("/proxies")))
+				.await?
+				.error_for_status()?
+				.json()
+				.await?;
+		Ok(res.into_proxies())
+	}
+
+	async fn proxy_delay(
+		&self,
+		name: &str,
+		timeout: u16,
+		url: &str,
+	) -> Result<u16, HttpError> {
+		let res: DelayResponse = send(self
+			.client
+			.get(self.endpoint(&format!(
+				"/proxies/{}/delay",
+				utf8_percent_encode(name, FRAGMENT)
+			)))
+			.query(&[
+				("timeout", timeout.to_string().as_str()),
+				("url", url),
+			])
+			// the controller answers once the test is done or timed out
+			.timeout(
+				Duration::from_millis(timeout as u64)
+					+ Duration::from_secs(5),
+			))
+		.await?
+		.error_for_status()?
+		.json()
+		.await?;
+		Ok(res.delay)
+	}
+
+	// the delay of each node in a group, measured by the controller in
+	// parallel; Clash Meta only
+	async fn group_delay(
+		&self,
+		name: &str,
+		timeout: u16,
+		url: &str,
+	) -> Result<HashMap<String, u16>, HttpError> {
+		let res = send(self
+			.client
+			.get(self.endpoint(&format!(
+				"/group/{}/delay",
+				utf8_percent_encode(name, FRAGMENT)
+			)))
+			.query(&[
+				("timeout", timeout.to_string().as_str()),
+				("url", url),
+			])
+			// the controller answers once the slowest node is done
+			.timeout(
+				Duration::from_millis(timeout as u64)
+					+ Duration::from_secs(5),
+			))
+		.await?
+		.error_for_status()?
+		.json()
+		.await?;
+		Ok(res)
+	}
+
+	// Parses while the body downloads instead of buffering all of it
+	// first like `.json()` does, which matters for large responses. The
+	// parser reads on a blocking thread, fed as the chunks arrive.
+	// Clash answers every endpoint in a single page, so there are no
+	// pages to follow.
+	async fn get_streamed<T: DeserializeOwned + Send + 'static>(
+		&self,
+		path: &str,
+	) -> Result<T, HttpError> {
+		let body =
+			SyncIoBridge::new(self.body(&self.client, path).await?);
+		let parse =
+			move || serde_json::from_reader(BufReader::new(body));
+		Ok(tokio::task::spawn_blocking(parse).await??)
+	}
+
+	async fn connections(&self) -> Result<Vec<Connection>, HttpError> {
+		// thousands of entries with busy clients
+		let res: ConnectionList =
+			self.get_streamed("/connections").await?;
+		Ok(res.connections.unwrap_or_default())
+	}
+
+	async fn close_connection(&self, id: &str) -> Result<(), HttpError> {
+		send(self.client.delete(self.endpoint(&format!(
+			"/connections/{}",
+			utf8_percent_encode(id, FRAGMENT)
+		))))
+		.await?
+		.error_for_status()?;
 		Ok(())
 	}
 
-	// fn providers(&self) -> Result<HashMap<String, Proxy>, Box<dyn Error>> {
-	// 	let res: ProviderList = self
-	// 		.client
-	// 		.get(format!("{}{}", self.url, "/providers/proxies"))
-	// 		.send()?
-	// 		.json()?;
-	// 	Ok(res.providers)
-	// }
+	async fn close_connections(&self) -> Result<(), HttpError> {
+		send(self.client.delete(self.endpoint("/connections")))
+			.await?
+			.error_for_status()?;
+		Ok(())
+	}
 
-	fn proxies(&self) -> Result<HashMap<String, Proxy>, Box<dyn Error>> {
-		let res: ProxyList = self
+	// resolves through Clash's own DNS; Premium and Meta only, the open
+	// source core answers 404
+	async fn dns_query(
+		&self,
+		name: &str,
+		kind: &str,
+	) -> Result<DnsAnswer, String> {
+		let res = send(self
 			.client
-			.get(format!("{}{}", self.url, "/proxies"))
-			.send()?
-			.json()?;
-		Ok(res.proxies)
+			.get(self.endpoint("/dns/query"))
+			.query(&[("name", name), ("type", kind)]))
+		.await
+		.map_err(|err| err.to_string())?;
+		let status = res.status();
+		if status.as_u16() == 404 {
+			return Err(String::from(
+				"DNS API not available, it needs Clash Premium or Meta",
+			));
+		}
+		if !status.is_success() {
+			return Err(res
+				.json::<ApiError>()
+				.await
+				.map(|err| err.message)
+				.unwrap_or_else(|_| status.to_string()));
+		}
+		res.json().await.map_err(|err| err.to_string())
+	}
+
+	async fn rules(&self) -> Result<Vec<Rule>, HttpError> {
+		let res: RuleList =
+			send(self.client.get(self.endpoint("/rules")))
+				.await?
+				.error_for_status()?
+				.json()
+				.await?;
+		Ok(res.rules)
 	}
 
-	fn update_proxy(
+	// the status of the answer and the controller's reason for a
+	// rejection, which is left to the caller since what it means depends
+	// on the group's type
+	async fn update_proxy(
 		&self,
 		provider: &str,
 		name: &str,
-	) -> Result<(), Box<dyn Error>> {
+	) -> Result<(StatusCode, String), HttpError> {
 		let body = HashMap::from([("name", name)]);
-		self.client
-			.put(format!(
-				"{}{}{}",
-				self.url,
-				"/proxies/",
-				utf8_percent_encode(provider, FRAGMENT),
-			))
-			.json(&body)
-			.send()?
-			.json()?;
+		let res = send(self
+			.client
+			.put(self.endpoint(&format!(
+				"/proxies/{}",
+				utf8_percent_encode(provider, FRAGMENT)
+			)))
+			.json(&body))
+		.await?;
+		let status = res.status();
+		if status.is_success() {
+			return Ok((status, String::new()));
+		}
+		let reason = res
+			.json::<ApiError>()
+			.await
+			.map(|err| err.message)
+			.unwrap_or_else(|_| status.to_string());
+		Ok((status, reason))
+	}
+}
+
+// `{"version": "v1.18.0"}`, plus `meta` or `premium` on the forks
+#[derive(Debug, Deserialize)]
+struct Version {
+	version: String,
+	#[serde(default)]
+	premium: bool,
+	#[serde(default)]
+	meta: bool,
+}
+
+impl fmt::Display for Version {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(&self.version)?;
+		if self.meta {
+			f.write_str(" Meta")?;
+		} else if self.premium {
+			f.write_str(" Premium")?;
+		}
 		Ok(())
 	}
 }
 
+// the body Clash answers a rejected request with
+#[derive(Deserialize)]
+struct ApiError {
+	message: String,
+}
+
 #[derive(Deserialize)]
 struct Config {
-	// TODO: enum "global, rule, direct"
-	mode: String,
+	mode: Mode,
+	// None when the core leaves them out
+	#[serde(rename = "allow-lan")]
+	allow_lan: Option<bool>,
+	#[serde(rename = "log-level")]
+	log_level: Option<LogLevel>,
+}
+
+// the fields of a PATCH /configs, those left None stay as they are
+#[derive(Default, Serialize)]
+struct ConfigPatch {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	mode: Option<Mode>,
+	#[serde(rename = "allow-lan", skip_serializing_if = "Option::is_none")]
+	allow_lan: Option<bool>,
+	#[serde(rename = "log-level", skip_serializing_if = "Option::is_none")]
+	log_level: Option<LogLevel>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum LogLevel {
+	Debug,
+	Info,
+	Warning,
+	Error,
+	Silent,
+}
+
+impl LogLevel {
+	fn next(self) -> Self {
+		match self {
+			LogLevel::Debug => LogLevel::Info,
+			LogLevel::Info => LogLevel::Warning,
+			LogLevel::Warning => LogLevel::Error,
+			LogLevel::Error => LogLevel::Silent,
+			LogLevel::Silent => LogLevel::Debug,
+		}
+	}
+}
+
+impl fmt::Display for LogLevel {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			LogLevel::Debug => "debug",
+			LogLevel::Info => "info",
+			LogLevel::Warning => "warning",
+			LogLevel::Error => "error",
+			LogLevel::Silent => "silent",
+		};
+		f.write_str(name)
+	}
+}
+
+// forks disagree on casing, e.g. `Rule`, `rule` or `RULE`, and newer cores
+// add their own modes such as `script`, kept as Other
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+#[serde(from = "String")]
+enum Mode {
+	Global,
+	Rule,
+	Direct,
+	Other(String),
+}
+
+impl Mode {
+	// the modes the General page offers, in order
+	const ALL: [Mode; 3] = [Mode::Global, Mode::Rule, Mode::Direct];
+
+	fn as_str(&self) -> &str {
+		match self {
+			Mode::Global => "global",
+			Mode::Rule => "rule",
+			Mode::Direct => "direct",
+			Mode::Other(name) => name,
+		}
+	}
+}
+
+impl From<String> for Mode {
+	fn from(name: String) -> Self {
+		match name.to_ascii_lowercase().as_str() {
+			"global" => Mode::Global,
+			"rule" => Mode::Rule,
+			"direct" => Mode::Direct,
+			_ => Mode::Other(name),
+		}
+	}
+}
+
+impl fmt::Display for Mode {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.as_str())
+	}
+}
+
+impl Serialize for Mode {
+	fn serialize<S: serde::Serializer>(
+		&self,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.as_str())
+	}
+}
+
+// Orders fetch results against mutations of the same resource. A fetch
+// sent before the latest mutation can answer after it, and applying its
+// result would show the mutation as reverted until the next refetch.
+#[derive(Default)]
+struct Sequence {
+	last: u64,
+	// ticket of the latest mutation
+	mutated: u64,
+}
+
+impl Sequence {
+	// taken when a fetch is sent, handed back with its result
+	fn fetch(&mut self) -> u64 {
+		self.last += 1;
+		self.last
+	}
+
+	fn mutate(&mut self) {
+		self.last += 1;
+		self.mutated = self.last;
+	}
+
+	// whether the result of fetch `ticket` is newer than every mutation
+	fn accepts(&self, ticket: u64) -> bool {
+		ticket > self.mutated
+	}
 }
 
 #[derive(Default)]
 struct GeneralState {
-	modes: Vec<String>,
+	// into Mode::ALL
 	index: usize,
 	config: Option<Config>,
+	// keeps the scroll position of the mode list between draws
+	list_state: ListState,
+	sequence: Sequence,
+	// mode as of the last check, to notice changes made elsewhere
+	known_mode: Option<Mode>,
+	// failed attempts and the last reason per mode, see unavailable
+	failures: HashMap<Mode, (usize, String)>,
 }
 
+// failed switches before a mode is no longer tried
+const MODE_ATTEMPTS: usize = 2;
+
 impl GeneralState {
 	fn new() -> Self {
 		Self {
-			modes: vec![
-				String::from("global"),
-				String::from("rule"),
-				String::from("direct"),
-			],
 			index: 0,
 			config: None,
+			list_state: ListState::default(),
+			sequence: Sequence::default(),
+			known_mode: None,
+			failures: HashMap::new(),
 		}
 	}
 
-	fn fetch_data(&mut self, http: &HttpClient) {
-		self.config = http.configs().ok();
+	fn receive(&mut self, ticket: u64, config: Option<Config>) {
+		if self.sequence.accepts(ticket) {
+			self.config = config;
+		}
 	}
 
 	fn next_mode(&mut self) {
-		let len = 3;
+		let len = Mode::ALL.len();
 		self.index = (self.index + 1) % len;
 	}
 
 	fn previous_mode(&mut self) {
-		let len = 3;
+		let len = Mode::ALL.len();
 		self.index = (self.index + len - 1) % len;
 	}
 
-	fn select_mode(&mut self, http: &HttpClient) {
-		http.update_config(&self.modes[self.index]).ok();
-		self.fetch_data(http);
+	fn is_active(&self, mode: &Mode) -> bool {
+		match &self.config {
+			Some(config) => &config.mode == mode,
+			_ => false,
+		}
+	}
+
+	// why `mode` keeps failing to apply, if it does
+	fn unavailable(&self, mode: &Mode) -> Option<&str> {
+		match self.failures.get(mode) {
+			Some((attempts, reason))
+				if *attempts >= MODE_ATTEMPTS =>
+			{
+				Some(reason)
+			}
+			_ => None,
+		}
+	}
+
+	// the patch switching to the mode under the cursor, unless that keeps
+	// failing
+	fn mode_patch(&self) -> Result<ConfigPatch, String> {
+		let mode = Mode::ALL[self.index].clone();
+		if let Some(reason) = self.unavailable(&mode) {
+			return Err(format!(
+				"{} is unavailable: {}",
+				mode, reason
+			));
+		}
+		Ok(ConfigPatch {
+			mode: Some(mode),
+			..ConfigPatch::default()
+		})
+	}
+
+	fn allow_lan_patch(&self) -> Result<ConfigPatch, String> {
+		let allow_lan = self.config.as_ref().and_then(|c| c.allow_lan);
+		Ok(ConfigPatch {
+			allow_lan: Some(
+				!allow_lan.ok_or("allow-lan is not loaded")?
+			),
+			..ConfigPatch::default()
+		})
+	}
+
+	fn log_level_patch(&self) -> Result<ConfigPatch, String> {
+		let level = self.config.as_ref().and_then(|c| c.log_level);
+		Ok(ConfigPatch {
+			log_level: Some(level
+				.ok_or("log-level is not loaded")?
+				.next()),
+			..ConfigPatch::default()
+		})
+	}
+
+	// `res` is how the controller answered `patch`, `config` the one
+	// fetched after it
+	fn patched(
+		&mut self,
+		ticket: u64,
+		patch: ConfigPatch,
+		res: Result<(), String>,
+		config: Option<Config>,
+	) -> Result<(), String> {
+		self.receive(ticket, config);
+		let (mode, res) = match (patch.mode, res) {
+			(Some(mode), res) => (mode, res),
+			(_, Ok(())) => return Ok(()),
+			(_, Err(err)) => {
+				return Err(format!(
+					"Could not change the config: {}",
+					err
+				))
+			}
+		};
+		self.known_mode = self.config.as_ref().map(|c| c.mode.clone());
+		let reason = match res {
+			Err(err) => err,
+			// some forks answer 204 and keep the old mode
+			Ok(()) if self.config.is_some()
+				&& !self.is_active(&mode) =>
+			{
+				String::from("ignored by the controller")
+			}
+			Ok(()) => {
+				self.failures.remove(&mode);
+				return Ok(());
+			}
+		};
+		let failures = self.failures.entry(mode.clone()).or_default();
+		failures.0 += 1;
+		failures.1 = reason.clone();
+		Err(format!("Could not switch to {}: {}", mode, reason))
+	}
+
+	// whether the mode changed since the last check, other than by us
+	fn changed_externally(&mut self) -> bool {
+		let mode = match &self.config {
+			Some(config) => &config.mode,
+			_ => return false,
+		};
+		let changed =
+			self.known_mode.as_ref().is_some_and(|m| m != mode);
+		self.known_mode = Some(mode.clone());
+		changed
 	}
 }
 
+// some forks return the map without the `proxies` wrapper
 #[derive(Deserialize)]
-struct ProxyList {
-	proxies: HashMap<String, Proxy>,
+#[serde(untagged)]
+enum ProxyList {
+	Wrapped { proxies: HashMap<String, Proxy> },
+	Bare(HashMap<String, Proxy>),
+}
+
+impl ProxyList {
+	fn into_proxies(self) -> HashMap<String, Proxy> {
+		match self {
+			ProxyList::Wrapped { proxies } => proxies,
+			ProxyList::Bare(proxies) => proxies,
+		}
+	}
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 struct Proxy {
+	// Selector, URLTest, Shadowsocks, ...
+	#[serde(default, rename = "type")]
+	kind: String,
 	all: Option<Vec<String>>,
 	name: String,
 	now: Option<String>,
+	#[serde(default)]
+	history: Vec<DelayHistory>,
+	// Clash Meta group hints, absent on OSS Clash
+	#[serde(default)]
+	hidden: bool,
+	// an image URL meant for web dashboards, not rendered here
+	#[serde(default)]
+	icon: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DelayResponse {
+	delay: u16,
+}
+
+// the parts of a /dns/query response worth showing
+#[derive(Debug, Deserialize)]
+struct DnsAnswer {
+	// the response code, 0 when the name resolved
+	#[serde(rename = "Status")]
+	status: u16,
+	// left out when there are no records
+	#[serde(rename = "Answer", default)]
+	answer: Vec<DnsRecord>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DnsRecord {
+	name: String,
+	#[serde(rename = "type")]
+	kind: u16,
+	#[serde(rename = "TTL")]
+	ttl: u32,
+	data: String,
+}
+
+impl DnsAnswer {
+	fn status_name(&self) -> String {
+		let name = match self.status {
+			0 => "NOERROR",
+			1 => "FORMERR",
+			2 => "SERVFAIL",
+			3 => "NXDOMAIN",
+			4 => "NOTIMP",
+			5 => "REFUSED",
+			code => return code.to_string(),
+		};
+		String::from(name)
+	}
+}
+
+impl DnsRecord {
+	fn kind_name(&self) -> String {
+		let name = match self.kind {
+			1 => "A",
+			2 => "NS",
+			5 => "CNAME",
+			6 => "SOA",
+			12 => "PTR",
+			15 => "MX",
+			16 => "TXT",
+			28 => "AAAA",
+			33 => "SRV",
+			65 => "HTTPS",
+			kind => return kind.to_string(),
+		};
+		String::from(name)
+	}
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct DelayHistory {
+	// 0 when the test timed out
+	delay: u16,
 }
 
 impl Proxy {
 	fn is_provider(&self) -> bool {
 		self.all.is_some()
 	}
+
+	// the other groups (URLTest, Fallback, LoadBalance, Relay) pick their
+	// node themselves
+	fn is_selector(&self) -> bool {
+		self.kind.is_empty()
+			|| self.kind.eq_ignore_ascii_case("Selector")
+	}
+
+	// in the controller's order
+	fn all_nodes(&self) -> Vec<&str> {
+		self.all.as_ref()
+			.map(|v| v.iter().map(|s| &**s).collect())
+			.unwrap_or_default()
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Latency {
+	Untested,
+	Timeout,
+	Delay(u16),
+}
+
+#[derive(Default, PartialEq, Clone, Copy)]
+enum LatencyDisplay {
+	#[default]
+	Number,
+	Bar,
+	// a dot in the color of the latency, for narrow terminals; its shape
+	// tells the buckets apart too with --accessible
+	Dot,
+}
+
+// delays at or above this fill the whole bar
+const BAR_MAX_DELAY: u16 = 1000;
+
+// delays below the first are fast, below the second slow, the rest bad
+const LATENCY_THRESHOLDS: [u16; 2] = [200, 500];
+
+fn latency_color(delay: u16, thresholds: [u16; 2]) -> Color {
+	match delay {
+		d if d < thresholds[0] => Color::Green,
+		d if d < thresholds[1] => Color::Yellow,
+		_ => Color::Red,
+	}
+}
+
+// `>` for the cursor and `*` for the active item, so neither relies on
+// color alone; nothing unless the accessible mode is on
+fn marker(accessible: bool, cursor: bool, active: bool) -> Span<'static> {
+	if !accessible {
+		return Span::raw("");
+	}
+	let cursor = if cursor { '>' } else { ' ' };
+	let active = if active { '*' } else { ' ' };
+	Span::raw(format!("{}{} ", cursor, active))
+}
+
+// a fixed width column so the node names stay aligned
+fn latency_span(
+	latency: Latency,
+	display: LatencyDisplay,
+	thresholds: [u16; 2],
+	width: u16,
+	accessible: bool,
+) -> Span<'static> {
+	let dim = Style::default().add_modifier(Modifier::DIM);
+	match display {
+		LatencyDisplay::Number => match latency {
+			Latency::Untested => {
+				Span::styled(format!("{:>7} ", "-"), dim)
+			}
+			Latency::Timeout => Span::styled(
+				format!("{:>7} ", "timeout"),
+				Style::default().fg(Color::Red),
+			),
+			Latency::Delay(delay) => Span::styled(
+				format!("{:>5}ms ", delay),
+				Style::default()
+					.fg(latency_color(delay, thresholds)),
+			),
+		},
+		LatencyDisplay::Bar => {
+			// shorter is faster
+			let width = (width / 5).clamp(5, 20) as usize;
+			let (bar, style) = match latency {
+				Latency::Untested => (" ".repeat(width), dim),
+				Latency::Timeout => (
+					"░".repeat(width),
+					Style::default().fg(Color::Red),
+				),
+				Latency::Delay(delay) => {
+					let delay = delay.min(BAR_MAX_DELAY)
+						as usize;
+					let max = BAR_MAX_DELAY as usize;
+					let filled = (delay * width)
+						.div_ceil(max)
+						.max(1);
+					let bar = format!(
+						"{}{}",
+						"█".repeat(filled),
+						" ".repeat(width - filled)
+					);
+					(
+						bar,
+						Style::default().fg(
+							latency_color(
+								delay as u16,
+								thresholds,
+							),
+						),
+					)
+				}
+			};
+			Span::styled(format!("{} ", bar), style)
+		}
+		LatencyDisplay::Dot => {
+			let color = match latency {
+				Latency::Untested => Color::DarkGray,
+				Latency::Timeout => Color::Red,
+				Latency::Delay(delay) => {
+					latency_color(delay, thresholds)
+				}
+			};
+			// the shape tells the buckets apart without the color
+			let dot = match (accessible, latency, color) {
+				(false, ..) => "● ",
+				(_, Latency::Untested, _) => "· ",
+				(_, Latency::Timeout, _) => "× ",
+				(_, _, Color::Green) => "● ",
+				(_, _, Color::Yellow) => "◐ ",
+				_ => "○ ",
+			};
+			Span::styled(dot, Style::default().fg(color))
+		}
+	}
+}
+
+#[derive(Default, PartialEq, Clone, Copy)]
+enum TabOrder {
+	// `group_order` from the config file, then by name
+	#[default]
+	Configured,
+	Alphabetical,
+	// fastest node in use first, untested and timed out groups last
+	Latency,
+}
+
+impl TabOrder {
+	fn next(self) -> Self {
+		match self {
+			TabOrder::Configured => TabOrder::Alphabetical,
+			TabOrder::Alphabetical => TabOrder::Latency,
+			TabOrder::Latency => TabOrder::Configured,
+		}
+	}
+}
+
+#[derive(Default, PartialEq, Clone, Copy)]
+enum ProxiesView {
+	// one tab per group
+	#[default]
+	Grouped,
+	// every node of every group in a single list
+	Flat,
 }
 
 #[derive(Default)]
 struct ProxiesState {
 	proxies: Option<HashMap<String, Proxy>>,
+	view: ProxiesView,
+	// resolved from the config file, per group
+	group_sorts: HashMap<String, SortOrder>,
+	pinned: HashMap<String, Vec<String>>,
+	// set at runtime, wins over the per group default
+	sort: Option<SortOrder>,
+	// tab order from the config file, unlisted groups follow by name
+	group_order: Vec<String>,
+	tab_order: TabOrder,
+	latency_display: LatencyDisplay,
+	// from the config file, LATENCY_THRESHOLDS when unset
+	latency_thresholds: Option<[u16; 2]>,
+	// reveals groups the config marks as hidden
+	show_hidden: bool,
+	// tested from here, 0 when timed out; newer than the history
+	delays: HashMap<String, u16>,
+	// the test URL behind each of `delays`
+	tested_with: HashMap<String, String>,
+	// first node picked for a comparison
+	marked: Option<String>,
+	// each group's `now` as of the last check
+	known_now: HashMap<String, String>,
+	sequence: Sequence,
+	// nodes flowed over several columns when the pane is wide enough
+	columns: bool,
+	// rows per column as last drawn, 0 when drawn as a single list
+	grid_rows: usize,
+	// keeps the scroll position of the node list between draws
+	list_state: ListState,
+	// where the nodes were last drawn and the first one shown, for
+	// mapping clicks to nodes
+	list_area: Rect,
+	list_offset: usize,
+	// node name substring, edited live while the search prompt is open
+	filter: String,
+	// the filter to go back to when the search is cancelled
+	kept_filter: String,
+	// the tab selected when last quitting, selected again once the
+	// proxies load
+	restore_group: Option<String>,
 	provider: usize,
 	proxy_index: usize,
 	proxies_len: usize,
@@ -203,76 +1283,482 @@ struct ProxiesState {
 }
 
 impl ProxiesState {
-	fn fetch_data(&mut self, http: &HttpClient) {
-		self.proxies = http.proxies().ok();
-		if self.proxies.is_none() {
-			self.provider = 0;
-			self.proxy_index = 0;
-			self.providers_len = 0;
-			self.proxies_len = 0;
-		} else {
-			self.provider = 0;
-
-			let providers = self.providers();
-			let len = providers.len();
-
-			if self.providers_len != 0 {
-				self.proxies_len = providers[self.provider]
-					.all
-					.as_ref()
-					.map(|v| v.len())
-					.unwrap_or_default();
-			} else {
-				self.proxies_len = 0;
+	fn new(config: &ConfigFile) -> Self {
+		let mut state = Self {
+			group_order: config.group_order.clone(),
+			latency_thresholds: config.latency_thresholds,
+			..Self::default()
+		};
+		for (group, group_config) in &config.groups {
+			if let Some(sort) = group_config.sort {
+				state.group_sorts.insert(group.clone(), sort);
 			}
+			if !group_config.order.is_empty() {
+				state.pinned.insert(
+					group.clone(),
+					group_config.order.clone(),
+				);
+			}
+		}
+		state
+	}
+
+	fn receive(
+		&mut self,
+		ticket: u64,
+		proxies: Option<HashMap<String, Proxy>>,
+	) {
+		if !self.sequence.accepts(ticket) {
+			return;
+		}
+		let highlighted = self.highlighted().map(|(group, node)| {
+			(group.to_owned(), node.to_owned())
+		});
+		self.proxies = proxies;
+
+		// the same group and node if they are still there, otherwise
+		// the same position as far as the new lists allow
+		let clamp = |index: usize, len: usize| {
+			index.min(len.saturating_sub(1))
+		};
+		let providers = self.providers();
+		let providers_len = providers.len();
+		let provider = highlighted
+			.as_ref()
+			.and_then(|(group, _)| {
+				providers.iter().position(|p| &p.name == group)
+			})
+			.unwrap_or(clamp(self.provider, providers_len));
+		let (len, index) = match self.view {
+			ProxiesView::Grouped => {
+				let nodes = providers
+					.get(provider)
+					.map(|p| self.nodes(p))
+					.unwrap_or_default();
+				let node = highlighted.map(|(_, node)| node);
+				let index = node.and_then(|node| {
+					nodes.iter().position(|n| *n == node)
+				});
+				(nodes.len(), index)
+			}
+			ProxiesView::Flat => {
+				let index = highlighted.and_then(
+					|(group, node)| {
+						self.position_of(&group, &node)
+					},
+				);
+				(self.flat_rows().len(), index)
+			}
+		};
+		self.provider = provider;
+		self.providers_len = providers_len;
+		self.proxies_len = len;
+		self.proxy_index =
+			index.unwrap_or(clamp(self.proxy_index, len));
+
+		if self.proxies.is_some() {
+			if let Some(group) = self.restore_group.take() {
+				self.focus_group(&group);
+			}
+		}
+	}
+
+	fn sort_order(&self, group: &str) -> SortOrder {
+		self.sort
+			.or_else(|| self.group_sorts.get(group).copied())
+			.unwrap_or(SortOrder::Alphabetical)
+	}
+
+	fn latency(&self, node: &str) -> Latency {
+		let proxy = self.proxies.as_ref().and_then(|p| p.get(node));
+		let delay = self.delays.get(node).copied().or_else(|| {
+			proxy.and_then(|p| p.history.last()).map(|h| h.delay)
+		});
+		match delay {
+			Some(0) => Latency::Timeout,
+			Some(delay) => Latency::Delay(delay),
+			_ => Latency::Untested,
+		}
+	}
+
+	// latest measured delay of a node, None if untested or timed out
+	fn delay(&self, node: &str) -> Option<u16> {
+		match self.latency(node) {
+			Latency::Delay(delay) => Some(delay),
+			_ => None,
+		}
+	}
+
+	fn hidden_len(&self) -> usize {
+		match &self.proxies {
+			Some(proxies) => proxies
+				.values()
+				.filter(|p| p.is_provider() && p.hidden)
+				.count(),
+			_ => 0,
+		}
+	}
+
+	// stays on the current group unless it just got hidden
+	fn toggle_hidden(&mut self) {
+		let current = self
+			.providers()
+			.get(self.provider)
+			.map(|p| p.name.clone());
+		self.show_hidden = !self.show_hidden;
+
+		let (provider, providers_len, proxies_len) = {
+			let providers = self.providers();
+			let provider = current
+				.and_then(|name| {
+					providers
+						.iter()
+						.position(|p| p.name == name)
+				})
+				.unwrap_or(0);
+			let proxies_len = match self.view {
+				ProxiesView::Grouped => providers
+					.get(provider)
+					.map(|p| self.nodes(p).len())
+					.unwrap_or_default(),
+				ProxiesView::Flat => self.flat_rows().len(),
+			};
+			(provider, providers.len(), proxies_len)
+		};
+		if provider != self.provider || self.view == ProxiesView::Flat {
 			self.proxy_index = 0;
+		}
+		self.provider = provider;
+		self.providers_len = providers_len;
+		self.proxies_len = proxies_len;
+	}
+
+	fn thresholds(&self) -> [u16; 2] {
+		self.latency_thresholds.unwrap_or(LATENCY_THRESHOLDS)
+	}
+
+	fn toggle_latency_display(&mut self) {
+		self.latency_display = match self.latency_display {
+			LatencyDisplay::Number => LatencyDisplay::Bar,
+			LatencyDisplay::Bar => LatencyDisplay::Dot,
+			LatencyDisplay::Dot => LatencyDisplay::Number,
+		};
+	}
+
+	// nodes of a group in display order, the only ordering rendering and
+	// selection may index into
+	fn nodes<'a>(&'a self, provider: &'a Proxy) -> Vec<&'a str> {
+		let mut nodes = provider.all_nodes();
+		let order = self.sort_order(&provider.name);
+		// the other orders break ties by name
+		if order != SortOrder::Original {
+			nodes.sort();
+		}
+		if !self.filter.is_empty() {
+			let filter = self.filter.to_lowercase();
+			nodes.retain(|node| {
+				node.to_lowercase().contains(&filter)
+			});
+		}
+		match order {
+			SortOrder::Alphabetical | SortOrder::Original => {}
+			SortOrder::Latency => nodes.sort_by_key(|node| {
+				match self.delay(node) {
+					Some(delay) => (false, delay),
+					_ => (true, 0),
+				}
+			}),
+			SortOrder::Pinned => {
+				let order = self.pinned.get(&provider.name);
+				nodes.sort_by_key(|node| {
+					order.and_then(|o| {
+						o.iter().position(|p| p == node)
+					})
+					.unwrap_or(usize::MAX)
+				});
+			}
+		}
+		nodes
+	}
+
+	fn cycle_sort(&mut self) {
+		let highlighted = self.highlighted().map(|(group, node)| {
+			(group.to_owned(), node.to_owned())
+		});
+		self.sort = match self.sort {
+			None => Some(SortOrder::Alphabetical),
+			Some(SortOrder::Pinned) => None,
+			Some(sort) => Some(sort.next()),
+		};
+
+		let index =
+			highlighted.and_then(|(group, node)| match self.view {
+				ProxiesView::Grouped => {
+					let provider = *self
+						.providers()
+						.get(self.provider)?;
+					self.nodes(provider)
+						.iter()
+						.position(|n| *n == node)
+				}
+				ProxiesView::Flat => {
+					self.position_of(&group, &node)
+				}
+			});
+		self.proxy_index = index.unwrap_or(0);
+	}
+
+	// keeps the highlighted node under the cursor if it still matches
+	fn set_filter(&mut self, filter: &str) {
+		let highlighted = self.highlighted().map(|(group, node)| {
+			(group.to_owned(), node.to_owned())
+		});
+		self.filter = filter.to_owned();
+
+		let (len, index) = match self.view {
+			ProxiesView::Grouped => {
+				let providers = self.providers();
+				let nodes = providers
+					.get(self.provider)
+					.map(|p| self.nodes(p))
+					.unwrap_or_default();
+				let index =
+					highlighted.and_then(|(_, node)| {
+						nodes.iter().position(|n| {
+							*n == node
+						})
+					});
+				(nodes.len(), index)
+			}
+			ProxiesView::Flat => {
+				let index = highlighted.and_then(
+					|(group, node)| {
+						self.position_of(&group, &node)
+					},
+				);
+				(self.flat_rows().len(), index)
+			}
+		};
+		self.proxies_len = len;
+		self.proxy_index = index.unwrap_or(0);
+	}
+
+	fn keep_filter(&mut self) {
+		self.kept_filter = self.filter.clone();
+	}
+
+	fn restore_filter(&mut self) {
+		let filter = self.kept_filter.clone();
+		self.set_filter(&filter);
+	}
+
+	fn flat_rows(&self) -> Vec<(&Proxy, &str)> {
+		self.providers()
+			.into_iter()
+			.flat_map(|provider| {
+				self.nodes(provider)
+					.into_iter()
+					.map(move |node| (provider, node))
+			})
+			.collect()
+	}
+
+	// the row of `node` under `group` in the flat view
+	fn position_of(&self, group: &str, node: &str) -> Option<usize> {
+		self.flat_rows()
+			.iter()
+			.position(|(p, n)| p.name == group && *n == node)
+	}
+
+	// the group and node under the cursor
+	fn highlighted(&self) -> Option<(&str, &str)> {
+		match self.view {
+			ProxiesView::Grouped => {
+				let provider =
+					*self.providers().get(self.provider)?;
+				let node = *self
+					.nodes(provider)
+					.get(self.proxy_index)?;
+				Some((&provider.name, node))
+			}
+			ProxiesView::Flat => {
+				let (provider, node) = *self
+					.flat_rows()
+					.get(self.proxy_index)?;
+				Some((&provider.name, node))
+			}
+		}
+	}
 
-			self.providers_len = len;
+	// keeps the highlighted node under the cursor across the switch
+	fn toggle_view(&mut self) {
+		let highlighted = self.highlighted().map(|(group, node)| {
+			(group.to_owned(), node.to_owned())
+		});
+
+		match self.view {
+			ProxiesView::Grouped => {
+				self.view = ProxiesView::Flat;
+				self.proxies_len = self.flat_rows().len();
+				self.proxy_index = highlighted
+					.and_then(|(group, node)| {
+						self.position_of(&group, &node)
+					})
+					.unwrap_or(0);
+			}
+			ProxiesView::Flat => {
+				self.view = ProxiesView::Grouped;
+				let (group, node) = highlighted.unzip();
+				let providers = self.providers();
+				let provider = group
+					.and_then(|group| {
+						providers.iter().position(|p| {
+							p.name == group
+						})
+					})
+					.unwrap_or(0);
+				let nodes = providers
+					.get(provider)
+					.map(|p| self.nodes(p))
+					.unwrap_or_default();
+				let len = nodes.len();
+				let index = node
+					.and_then(|node| {
+						nodes.iter().position(|n| {
+							*n == node
+						})
+					})
+					.unwrap_or(0);
+				self.provider = provider;
+				self.proxies_len = len;
+				self.proxy_index = index;
+			}
 		}
 	}
 
 	fn providers(&self) -> Vec<&Proxy> {
 		let mut providers = if let Some(proxies) = &self.proxies {
-			proxies.values().filter(|p| p.is_provider()).collect()
+			proxies.values()
+				.filter(|p| p.is_provider())
+				.filter(|p| self.show_hidden || !p.hidden)
+				.collect()
 		} else {
 			Vec::new()
 		};
 
-		providers.sort_by(|x, y| x.name.cmp(&y.name));
+		let rank = |p: &Proxy| match self.tab_order {
+			// listed groups first, in the configured order
+			TabOrder::Configured => self
+				.group_order
+				.iter()
+				.position(|name| name == &p.name)
+				.unwrap_or(usize::MAX),
+			TabOrder::Alphabetical => 0,
+			TabOrder::Latency => p
+				.now
+				.as_deref()
+				.and_then(|now| self.delay(self.resolve(now)))
+				.map_or(usize::MAX, usize::from),
+		};
+		providers.sort_by(|x, y| {
+			rank(x).cmp(&rank(y)).then_with(|| x.name.cmp(&y.name))
+		});
 
 		providers
 	}
 
+	// follows the `now` of nested groups down to the node in use
+	fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+		let mut name = name;
+		if let Some(proxies) = &self.proxies {
+			// bounded in case groups refer to each other
+			for _ in 0..proxies.len() {
+				match proxies
+					.get(name)
+					.and_then(|p| p.now.as_deref())
+				{
+					Some(now) => name = now,
+					_ => break,
+				}
+			}
+		}
+		name
+	}
+
 	fn next_tab(&mut self) {
+		if self.view == ProxiesView::Flat {
+			return;
+		}
 		if self.providers_len == 0 {
 			self.provider = 0;
 			return;
 		}
 		let index = self.provider + 1;
-		self.provider = index % self.providers_len;
+		self.select_tab(index % self.providers_len);
+	}
+
+	fn select_tab(&mut self, index: usize) {
+		self.provider = index;
+		self.list_state = ListState::default();
+		self.list_offset = 0;
 		let providers = self.providers();
-		self.proxies_len = providers[self.provider]
-			.all
-			.as_ref()
-			.map(|v| v.len())
-			.unwrap_or_default();
+		self.proxies_len = self.nodes(providers[self.provider]).len();
 		self.proxy_index = 0;
 	}
 
+	fn cycle_tab_order(&mut self) {
+		let group = self.group_name();
+		self.tab_order = self.tab_order.next();
+		self.keep_tab(group);
+	}
+
+	fn record_delay(&mut self, node: String, delay: u16, url: String) {
+		let group = self.group_name();
+		self.delays.insert(node.clone(), delay);
+		self.tested_with.insert(node, url);
+		if self.tab_order == TabOrder::Latency {
+			self.keep_tab(group);
+		}
+	}
+
+	// the group of the selected tab
+	fn group_name(&self) -> Option<String> {
+		let provider = *self.providers().get(self.provider)?;
+		Some(provider.name.clone())
+	}
+
+	// keeps `group` selected after the tabs were reordered
+	fn keep_tab(&mut self, group: Option<String>) {
+		let index = group.and_then(|group| {
+			self.providers().iter().position(|p| p.name == group)
+		});
+		if let Some(index) = index {
+			self.provider = index;
+		}
+	}
+
+	// shows the named group's tab if it is there
+	fn focus_group(&mut self, name: &str) {
+		if self.view == ProxiesView::Flat {
+			return;
+		}
+		let index =
+			self.providers().iter().position(|p| p.name == name);
+		if let Some(index) = index {
+			self.select_tab(index);
+		}
+	}
+
 	fn previous_tab(&mut self) {
+		if self.view == ProxiesView::Flat {
+			return;
+		}
 		if self.providers_len == 0 {
 			self.provider = 0;
 			return;
 		}
 		let index = self.provider + self.providers_len - 1;
-		self.provider = index % self.providers_len;
-		let providers = self.providers();
-		self.proxies_len = providers[self.provider]
-			.all
-			.as_ref()
-			.map(|v| v.len())
-			.unwrap_or_default();
-		self.proxy_index = 0;
+		self.select_tab(index % self.providers_len);
 	}
 
 	fn next_proxy(&mut self) {
@@ -293,444 +1779,7478 @@ impl ProxiesState {
 		self.proxy_index = index % self.proxies_len;
 	}
 
-	fn select_proxy(&mut self, http: &HttpClient) {
-		if self.providers_len == 0 || self.proxies_len == 0 {
-			return;
+	// the node drawn at a screen position, if any
+	fn node_at(&self, x: u16, y: u16) -> Option<usize> {
+		let area = self.list_area;
+		if !inside(area, x, y) {
+			return None;
 		}
-
-		let providers = self.providers();
-		let provider_index = self.provider;
-		let provider = match providers.get(provider_index) {
-			Some(provider) => provider,
-			_ => return,
+		let row = (y - area.y) as usize;
+		let index = if self.grid_rows > 0 {
+			let columns = (area.width / GRID_COLUMN_WIDTH).max(1)
+				as usize;
+			let page = self.grid_rows * columns;
+			let first = self.proxy_index / page * page;
+			let column = (x - area.x) as usize * columns
+				/ area.width as usize;
+			first + column * self.grid_rows + row
+		} else {
+			self.list_offset + row
 		};
-		let proxy_index = self.proxy_index;
-		let name = match &provider.all {
-			Some(proxies) => {
-				let mut proxies: Vec<_> =
-					proxies.iter().map(|s| &**s).collect();
-				proxies.sort();
+		(index < self.proxies_len).then_some(index)
+	}
 
-				match proxies.get(proxy_index) {
-					Some(proxy) => *proxy,
-					_ => return,
-				}
-			}
-			_ => return,
-		};
+	fn toggle_columns(&mut self) {
+		self.columns = !self.columns;
+	}
 
-		http.update_proxy(&provider.name, name).ok();
-		self.fetch_data(http);
+	// the same row a column over, nothing to move to in a single list
+	fn next_column(&mut self) {
+		let index = self.proxy_index + self.grid_rows;
+		if self.grid_rows > 0 && index < self.proxies_len {
+			self.proxy_index = index;
+		}
+	}
 
-		if self.providers_len == 0 || self.proxies_len == 0 {
-			return;
+	fn previous_column(&mut self) {
+		if self.grid_rows > 0 && self.proxy_index >= self.grid_rows {
+			self.proxy_index -= self.grid_rows;
 		}
-		if provider_index < self.providers_len {
-			self.provider = provider_index;
+	}
+
+	// the group and node under the cursor, if there are any
+	fn selection(&self) -> Option<(String, String)> {
+		if self.providers_len == 0 || self.proxies_len == 0 {
+			return None;
 		}
-		if proxy_index < self.proxies_len {
-			self.proxy_index = proxy_index;
+		let (group, name) = self.highlighted()?;
+		Some((group.to_owned(), name.to_owned()))
+	}
+
+	// the type of `group` when it picks its node itself
+	fn auto_kind(&self, group: &str) -> Option<String> {
+		self.proxies
+			.as_ref()?
+			.get(group)
+			.filter(|p| !p.is_selector())
+			.map(|p| p.kind.clone())
+	}
+
+	// in the controller's order, empty until loaded
+	fn global_nodes(&self) -> Vec<&str> {
+		self.proxies
+			.as_ref()
+			.and_then(|p| p.get(GLOBAL))
+			.map(|global| global.all_nodes())
+			.unwrap_or_default()
+	}
+
+	fn now(&self, group: &str) -> Option<&str> {
+		self.proxies.as_ref()?.get(group)?.now.as_deref()
+	}
+
+	// groups whose `now` changed since the last check, other than by us
+	fn changed_externally(&mut self) -> Vec<String> {
+		let proxies = match &self.proxies {
+			Some(proxies) => proxies,
+			_ => return Vec::new(),
+		};
+		let mut changed = Vec::new();
+		for (group, proxy) in proxies {
+			let now = match &proxy.now {
+				Some(now) => now,
+				_ => continue,
+			};
+			match self.known_now.get(group) {
+				Some(known) if known != now => {
+					changed.push(group.clone())
+				}
+				Some(_) => continue,
+				_ => {}
+			}
+			self.known_now.insert(group.clone(), now.clone());
 		}
+		changed.sort();
+		changed
 	}
 }
 
-struct App {
-	http: HttpClient,
-	routes: Vec<Route>,
-	page: usize,
-	focus: Pane,
-	general_state: GeneralState,
-	proxies_state: ProxiesState,
+#[derive(Deserialize)]
+struct RuleList {
+	rules: Vec<Rule>,
 }
 
-impl App {
-	fn new(base_url: &str) -> Self {
-		let routes = vec![
-			Route::General,
-			Route::Proxies,
-			Route::Rules,
-			Route::Connections,
-			Route::Logs,
-		];
+#[derive(Debug, Deserialize, Serialize)]
+struct Rule {
+	#[serde(rename = "type")]
+	kind: String,
+	payload: String,
+	proxy: String,
+	// rule-provider the rule came from, only reported by some forks
+	#[serde(default, alias = "provider")]
+	source: Option<String>,
+}
 
-		Self {
-			http: HttpClient::new(base_url),
-			routes,
-			page: 0,
-			focus: Pane::Menu,
-			general_state: GeneralState::new(),
-			proxies_state: ProxiesState::default(),
+impl Rule {
+	fn source(&self) -> Option<&str> {
+		match &self.source {
+			Some(source) if !source.is_empty() => Some(source),
+			// Clash Meta reports provider rules as `RuleSet,<provider>`
+			_ if self.kind.eq_ignore_ascii_case("RuleSet") => {
+				Some(&self.payload)
+			}
+			_ => None,
 		}
 	}
+}
 
-	fn navigate(&mut self, page: usize) {
-		self.page = page % self.routes.len();
-		self.fetch_data();
+// CSV when the path ends in `.csv`, YAML otherwise. Written rule by rule
+// so a huge rule set is never held as one big string.
+fn export_rules(rules: &[Rule], path: &Path) -> Result<usize, Box<dyn Error>> {
+	let mut file = BufWriter::new(File::create(path)?);
+	let csv = path
+		.extension()
+		.is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+	if csv {
+		let mut writer = csv::Writer::from_writer(file);
+		for rule in rules {
+			writer.serialize(rule)?;
+		}
+		writer.flush()?;
+	} else {
+		if rules.is_empty() {
+			writeln!(file, "[]")?;
+		}
+		for rule in rules {
+			serde_yaml::to_writer(&mut file, &[rule])?;
+		}
+		file.flush()?;
 	}
+	Ok(rules.len())
+}
 
-	fn next_menu(&mut self) {
-		let page = self.page + 1;
-		self.page = page % self.routes.len();
-		self.fetch_data();
+fn expand_home(path: &str) -> PathBuf {
+	match (path.strip_prefix("~/"), env::var_os("HOME")) {
+		(Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+		_ => PathBuf::from(path),
 	}
+}
 
-	fn previous_menu(&mut self) {
-		let page = self.page + self.routes.len() - 1;
-		self.page = page % self.routes.len();
-		self.fetch_data();
+#[derive(Default, PartialEq, Clone, Copy)]
+enum SourceFilter {
+	#[default]
+	All,
+	Inline,
+	Provider,
+}
+
+impl SourceFilter {
+	fn next(self) -> Self {
+		match self {
+			SourceFilter::All => SourceFilter::Inline,
+			SourceFilter::Inline => SourceFilter::Provider,
+			SourceFilter::Provider => SourceFilter::All,
+		}
 	}
 
-	fn fetch_data(&mut self) {
-		let route = match self.route() {
-			Some(route) => route,
-			_ => return,
-		};
-		match route {
-			Route::General => {
-				self.general_state.fetch_data(&self.http)
-			}
-			Route::Proxies => {
-				self.proxies_state.fetch_data(&self.http)
-			}
-			Route::Rules => {}
-			Route::Connections => {}
-			Route::Logs => {}
+	fn accepts(self, rule: &Rule) -> bool {
+		match self {
+			SourceFilter::All => true,
+			SourceFilter::Inline => rule.source().is_none(),
+			SourceFilter::Provider => rule.source().is_some(),
 		}
 	}
+}
 
-	fn route(&self) -> Option<&Route> {
-		self.routes.get(self.page)
+impl fmt::Display for SourceFilter {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			SourceFilter::All => "all",
+			SourceFilter::Inline => "inline",
+			SourceFilter::Provider => "provider",
+		};
+		f.write_str(name)
 	}
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-	// TODO: log
+#[derive(Default)]
+struct RulesState {
+	rules: Option<Vec<Rule>>,
+	filter: SourceFilter,
+	host: String,
+	// first of the filtered rules shown, moved with j and k
+	offset: usize,
+	// rules shown when last drawn, for paging
+	rows: usize,
+	fetched: Option<Instant>,
+}
 
-	let cli = Cli::parse();
-	let base_url =
-		cli.base_url.as_deref().unwrap_or("http://localhost:9090");
+// rules rarely change and can number in the thousands
+const RULES_REFRESH: Duration = Duration::from_secs(10);
 
-	enable_raw_mode()?;
-	let mut stdout = io::stdout();
-	execute!(stdout, EnterAlternateScreen)?;
-	let backend = CrosstermBackend::new(stdout);
-	let mut terminal = Terminal::new(backend)?;
+impl RulesState {
+	fn receive(&mut self, rules: Option<Vec<Rule>>) {
+		self.rules = rules;
+		self.offset = self.offset.min(self.last());
+	}
 
-	let tick_rate = Duration::from_secs(1);
-	let app = App::new(base_url);
-	let res = run_app(&mut terminal, app, tick_rate);
+	fn stale(&self) -> bool {
+		self.fetched.is_none_or(|at| at.elapsed() >= RULES_REFRESH)
+	}
 
-	disable_raw_mode()?;
-	execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-	terminal.show_cursor()?;
+	fn last(&self) -> usize {
+		self.filtered().len().saturating_sub(1)
+	}
 
-	if let Err(err) = res {
-		println!("{:?}", err)
+	fn scroll_down(&mut self) {
+		self.offset = (self.offset + 1).min(self.last());
 	}
 
-	Ok(())
-}
+	fn scroll_up(&mut self) {
+		self.offset = self.offset.saturating_sub(1);
+	}
 
-fn run_app<B: Backend>(
-	terminal: &mut Terminal<B>,
-	mut app: App,
-	tick_rate: Duration,
-) -> io::Result<()> {
-	let mut last_tick = Instant::now();
-	loop {
-		terminal.draw(|f| render(f, &mut app))?;
+	fn cycle_filter(&mut self) {
+		self.filter = self.filter.next();
+		self.offset = 0;
+	}
 
-		let timeout = tick_rate
-			.checked_sub(last_tick.elapsed())
-			.unwrap_or_else(|| Duration::from_secs(0));
+	fn filtered(&self) -> Vec<&Rule> {
+		match &self.rules {
+			Some(rules) => rules
+				.iter()
+				.filter(|r| self.filter.accepts(r))
+				.collect(),
+			_ => Vec::new(),
+		}
+	}
 
-		if event::poll(timeout)? {
-			if let Event::Key(key) = event::read()? {
-				let res = process_key(key.code, &mut app);
-				match res {
-					ProcessResult::Noop => {}
-					ProcessResult::Ok => return Ok(()),
-				}
-			}
+	fn matched(&self) -> Option<&Rule> {
+		if self.host.is_empty() {
+			return None;
 		}
+		matcher::find(self.rules.as_deref()?, &self.host)
+	}
+}
 
-		if last_tick.elapsed() >= tick_rate {
-			last_tick = Instant::now();
+#[derive(Deserialize)]
+struct ProviderList {
+	providers: HashMap<String, ProxyProvider>,
+}
+
+// a proxy provider from the config, usually a subscription
+#[derive(Deserialize)]
+struct ProxyProvider {
+	name: String,
+	// HTTP or File, Compatible for the ones Clash makes for each group
+	#[serde(default, rename = "vehicleType")]
+	vehicle_type: String,
+	#[serde(rename = "updatedAt")]
+	updated_at: Option<String>,
+	// from the subscription-userinfo header, Clash Meta only
+	#[serde(rename = "subscriptionInfo")]
+	subscription_info: Option<SubscriptionInfo>,
+	// with the delay history of the last health check
+	#[serde(default)]
+	proxies: Vec<Proxy>,
+}
+
+impl ProxyProvider {
+	// nodes that answered their latest check, None when none was checked
+	fn alive(&self) -> Option<(usize, usize)> {
+		let checked: Vec<_> = self
+			.proxies
+			.iter()
+			.filter_map(|p| p.history.last())
+			.collect();
+		if checked.is_empty() {
+			return None;
 		}
+		let alive = checked.iter().filter(|h| h.delay != 0).count();
+		Some((alive, self.proxies.len()))
 	}
 }
 
-enum ProcessResult {
-	Noop,
-	Ok,
-	// Error,
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+struct SubscriptionInfo {
+	upload: u64,
+	download: u64,
+	// bytes, 0 when unlimited
+	total: u64,
+	// unix seconds, 0 when it never expires
+	expire: u64,
 }
 
-fn process_key(code: KeyCode, app: &mut App) -> ProcessResult {
-	if let KeyCode::Char('q') = code {
-		return ProcessResult::Ok;
+// days before expiry a subscription is shown as expiring
+const EXPIRY_WARNING_DAYS: u64 = 7;
+
+impl SubscriptionInfo {
+	fn used(&self) -> u64 {
+		self.upload + self.download
 	}
 
-	let focus = &app.focus;
-	match focus {
-		Pane::Menu => match code {
-			KeyCode::Char('j') => app.next_menu(),
-			KeyCode::Char('k') => app.previous_menu(),
-			KeyCode::Char('l') => match app.route() {
-				Some(&Route::Proxies) => {
-					app.focus = Pane::Proxies;
-					app.fetch_data()
-				}
-				Some(&Route::General) => {
-					app.focus = Pane::General;
-					app.fetch_data()
-				}
-				_ => {}
-			},
-			KeyCode::Char('1') => app.navigate(0),
-			KeyCode::Char('2') => app.navigate(1),
-			KeyCode::Char('3') => app.navigate(2),
-			KeyCode::Char('4') => app.navigate(3),
-			KeyCode::Char('5') => app.navigate(4),
-			_ => {}
-		},
-		Pane::General => match code {
-			KeyCode::Esc | KeyCode::Char('h') => {
-				app.focus = Pane::Menu;
-			}
-			KeyCode::Char(' ') => {
-				app.general_state.select_mode(&app.http);
-			}
-			KeyCode::Char('j') => {
-				app.general_state.next_mode();
-			}
-			KeyCode::Char('k') => {
-				app.general_state.previous_mode();
-			}
-			_ => {}
-		},
-		Pane::Proxies => match code {
-			KeyCode::Esc | KeyCode::Char('h') => {
-				app.focus = Pane::Menu;
-			}
-			KeyCode::Char(' ') => {
-				app.proxies_state.select_proxy(&app.http);
-			}
-			KeyCode::Char('j') => {
-				app.proxies_state.next_proxy();
-			}
-			KeyCode::Char('k') => {
-				app.proxies_state.previous_proxy();
-			}
-			KeyCode::Char('H') => {
-				app.proxies_state.previous_tab();
-			}
-			KeyCode::Char('L') => {
-				app.proxies_state.next_tab();
-			}
-			_ => {}
-		},
-		// _ => match code {
-		// 	KeyCode::Esc | KeyCode::Char('h') => {
-		// 		app.focus = Pane::Menu;
-		// 	}
-		// 	_ => {}
-		// },
+	// what needs attention, if anything, and how urgently
+	fn warning(&self, now: u64) -> Option<(&'static str, Color)> {
+		if self.expire != 0 && self.expire <= now {
+			Some(("expired", Color::Red))
+		} else if self.total != 0 && self.used() >= self.total {
+			Some(("over quota", Color::Red))
+		} else if self.expire != 0
+			&& self.expire - now <= EXPIRY_WARNING_DAYS * 86400
+		{
+			Some(("expires soon", Color::Yellow))
+		} else {
+			None
+		}
 	}
+}
 
-	ProcessResult::Noop
+#[derive(Default)]
+struct ProvidersState {
+	// by name, without the Compatible ones
+	providers: Option<Vec<ProxyProvider>>,
+	index: usize,
+	// what is being done to a provider until its request returns
+	busy: HashMap<String, &'static str>,
 }
 
-fn render<B: Backend>(f: &mut Frame<B>, app: &mut App) {
-	let chunks = Layout::default()
-		.direction(Direction::Horizontal)
-		.constraints(
-			[
-				Constraint::Percentage(30),
-				Constraint::Percentage(70),
-			]
-			.as_ref(),
-		)
-		.split(f.size());
+impl ProvidersState {
+	fn receive(
+		&mut self,
+		providers: Option<HashMap<String, ProxyProvider>>,
+	) {
+		self.providers = providers.map(|providers| {
+			let mut providers: Vec<_> = providers
+				.into_values()
+				.filter(|p| p.vehicle_type != "Compatible")
+				.collect();
+			providers.sort_by(|a, b| a.name.cmp(&b.name));
+			providers
+		});
+		self.index = self.index.min(self.len().saturating_sub(1));
+	}
 
-	let items = &app.routes;
-	let page = app.page;
-	let menu = draw_menu(items, page);
-	f.render_widget(menu, chunks[0]);
+	fn len(&self) -> usize {
+		self.providers.as_ref().map_or(0, Vec::len)
+	}
 
-	let route = &app.routes.get(app.page).unwrap_or(&Route::General);
-	let general_state = &mut app.general_state;
-	let proxies_state = &mut app.proxies_state;
-	let focus = &app.focus;
-	render_main(f, route, general_state, proxies_state, focus, chunks[1]);
+	fn next(&mut self) {
+		if self.len() != 0 {
+			self.index = (self.index + 1) % self.len();
+		}
+	}
+
+	fn previous(&mut self) {
+		if self.len() != 0 {
+			self.index = (self.index + self.len() - 1) % self.len();
+		}
+	}
+
+	fn selected(&self) -> Option<&ProxyProvider> {
+		self.providers.as_ref()?.get(self.index)
+	}
 }
 
-fn draw_menu(items: &[Route], page: usize) -> List<'_> {
-	let items: Vec<_> = items
-		.iter()
-		.map(|route| {
-			let name = route.to_string();
+#[derive(Deserialize)]
+struct ConnectionList {
+	// null rather than empty when there are no connections
+	connections: Option<Vec<Connection>>,
+}
 
-			let style = if items.get(page) == Some(route) {
-				Style::default()
-					.bg(Color::LightBlue)
-					.add_modifier(Modifier::BOLD)
-			} else {
-				Style::default()
-			};
+#[derive(Deserialize)]
+struct Connection {
+	id: String,
+	metadata: Metadata,
+	upload: u64,
+	download: u64,
+	#[serde(default)]
+	chains: Vec<String>,
+	// RFC 3339, when the connection was opened
+	#[serde(default)]
+	start: String,
+}
 
-			let spans = Spans::from(Span::styled(
-				name,
-				Style::default().add_modifier(Modifier::ITALIC),
-			));
+#[derive(Deserialize)]
+struct Metadata {
+	#[serde(default)]
+	host: String,
+	#[serde(default, rename = "destinationIP")]
+	destination_ip: String,
+	// Clash Meta and Premium on some platforms
+	#[serde(default)]
+	process: String,
+	// "tcp" or "udp"
+	#[serde(default)]
+	network: String,
+}
 
-			ListItem::new(spans).style(style)
-		})
-		.collect();
+impl Connection {
+	// unix seconds
+	fn started(&self) -> Option<u64> {
+		parse_timestamp(&self.start)
+	}
 
-	let menu = List::new(items)
-		.block(Block::default().borders(Borders::ALL).title("Clash"));
+	fn host(&self) -> &str {
+		if self.metadata.host.is_empty() {
+			&self.metadata.destination_ip
+		} else {
+			&self.metadata.host
+		}
+	}
 
-	menu
+	fn group_key(&self, grouping: Grouping) -> &str {
+		let key = match grouping {
+			Grouping::Host => self.host(),
+			_ => &self.metadata.process,
+		};
+		if key.is_empty() {
+			"(unknown)"
+		} else {
+			key
+		}
+	}
 }
 
-fn render_main<'a, B: Backend>(
-	f: &'a mut Frame<B>,
-	route: &'a Route,
-	general_state: &mut GeneralState,
-	proxies_state: &mut ProxiesState,
-	focus: &'a Pane,
-	rect: Rect,
-) {
-	match route {
-		Route::General => render_general(f, general_state, focus, rect),
-		Route::Proxies => render_proxies(f, proxies_state, focus, rect),
-		Route::Rules => f.render_widget(draw_rules(), rect),
-		Route::Connections => f.render_widget(draw_connections(), rect),
-		Route::Logs => f.render_widget(draw_logs(), rect),
-	}
+#[derive(Default, Clone, Copy, PartialEq)]
+enum NetworkFilter {
+	#[default]
+	All,
+	Tcp,
+	Udp,
 }
 
-fn render_general<'a, B: Backend>(
-	f: &'a mut Frame<B>,
-	state: &mut GeneralState,
-	focus: &'a Pane,
-	rect: Rect,
-) {
-	let items: Vec<_> = state
-		.modes
-		.iter()
-		.map(|name| {
-			let mut style = Style::default();
-			let mode = state.config.as_ref().map(|c| &c.mode);
-			if Some(name) == mode {
-				style = style
-					.fg(Color::LightRed)
-					.add_modifier(Modifier::BOLD);
+impl NetworkFilter {
+	fn next(self) -> Self {
+		match self {
+			NetworkFilter::All => NetworkFilter::Tcp,
+			NetworkFilter::Tcp => NetworkFilter::Udp,
+			NetworkFilter::Udp => NetworkFilter::All,
+		}
+	}
+
+	fn accepts(self, connection: &Connection) -> bool {
+		let network = &connection.metadata.network;
+		match self {
+			NetworkFilter::All => true,
+			NetworkFilter::Tcp => {
+				network.eq_ignore_ascii_case("tcp")
 			}
-			if name == &state.modes[state.index]
-				&& focus == &Pane::General
-			{
-				style = style.bg(Color::LightBlue);
+			NetworkFilter::Udp => {
+				network.eq_ignore_ascii_case("udp")
 			}
+		}
+	}
+}
 
-			let spans = Spans::from(name.as_ref());
+#[derive(Default, Clone, Copy, PartialEq)]
+enum Grouping {
+	#[default]
+	None,
+	Process,
+	Host,
+}
 
-			ListItem::new(spans).style(style)
-		})
-		.collect();
+impl Grouping {
+	fn next(self) -> Self {
+		match self {
+			Grouping::None => Grouping::Process,
+			Grouping::Process => Grouping::Host,
+			Grouping::Host => Grouping::None,
+		}
+	}
+}
 
-	let block = Block::default().borders(Borders::ALL).title("General");
-	let list = List::new(items).block(block);
-	f.render_widget(list, rect);
+// the column the connections table is sorted by, the controller's order
+// when None
+#[derive(Default, Clone, Copy, PartialEq)]
+enum ConnectionSort {
+	#[default]
+	None,
+	Download,
+	Upload,
+	Duration,
 }
 
-fn render_proxies<'a, B: Backend>(
-	f: &'a mut Frame<B>,
-	state: &mut ProxiesState,
-	focus: &'a Pane,
-	rect: Rect,
-) {
-	let chunks = Layout::default()
-		.direction(Direction::Vertical)
-		.constraints(
-			[Constraint::Length(3), Constraint::Min(0)].as_ref(),
-		)
-		.split(rect);
+impl ConnectionSort {
+	fn next(self) -> Self {
+		match self {
+			ConnectionSort::None => ConnectionSort::Download,
+			ConnectionSort::Download => ConnectionSort::Upload,
+			ConnectionSort::Upload => ConnectionSort::Duration,
+			ConnectionSort::Duration => ConnectionSort::None,
+		}
+	}
 
-	let block = Block::default().style(Style::default());
-	f.render_widget(block, rect);
+	fn compare(self, a: &Connection, b: &Connection) -> cmp::Ordering {
+		match self {
+			ConnectionSort::None => cmp::Ordering::Equal,
+			ConnectionSort::Download => a.download.cmp(&b.download),
+			ConnectionSort::Upload => a.upload.cmp(&b.upload),
+			// the earlier start has been open longer
+			ConnectionSort::Duration => {
+				b.started().cmp(&a.started())
+			}
+		}
+	}
+}
 
-	if state.providers_len == 0 {
-		return;
+// connections sharing a process or host, with their summed traffic
+struct ConnectionGroup<'a> {
+	key: &'a str,
+	count: usize,
+	upload: u64,
+	download: u64,
+}
+
+#[derive(Default)]
+struct ConnectionsState {
+	connections: Vec<Connection>,
+	index: usize,
+	// recent (upload, download) bytes per second, by connection id
+	rates: HashMap<String, VecDeque<(u64, u64)>>,
+	updated: Option<Instant>,
+	grouping: Grouping,
+	// cursor into `groups()` while grouped
+	group_index: usize,
+	network: NetworkFilter,
+	sort: ConnectionSort,
+	// the largest first unless set
+	ascending: bool,
+	// host or IP substring, edited live while the search prompt is open
+	filter: String,
+	// the filter to go back to when the search is cancelled
+	kept_filter: String,
+	// rows shown when last drawn, for paging
+	rows: usize,
+	// a fetch is on its way, refreshes wait for it
+	fetching: bool,
+}
+
+const RATE_SAMPLES: usize = 60;
+
+impl ConnectionsState {
+	fn receive(&mut self, connections: Option<Vec<Connection>>) {
+		self.fetching = false;
+		self.update(connections.unwrap_or_default());
 	}
 
-	let providers = state.providers();
+	// the list reorders as connections come and go, so the cursor
+	// follows the connection id rather than its position
+	fn update(&mut self, connections: Vec<Connection>) {
+		let new = self.arrange(&connections);
+		let index = reselect(&self.visible(), self.index, &new);
+		self.index = index;
+		self.update_rates(&connections);
+		self.connections = connections;
+	}
 
-	let titles: Vec<_> = providers
-		.iter()
-		.skip(state.provider)
-		.map(|p| Spans::from(p.name.as_ref()))
-		.collect();
+	fn accepts(&self, connection: &Connection) -> bool {
+		let filter = self.filter.to_lowercase();
+		let matches =
+			|text: &str| text.to_lowercase().contains(&filter);
+		self.network.accepts(connection)
+			&& (matches(&connection.metadata.host)
+				|| matches(&connection.metadata.destination_ip))
+	}
 
-	let mut tabs = Tabs::new(titles)
-		.block(Block::default().borders(Borders::ALL).title("Proxies"))
-		.style(Style::default())
-		.highlight_style(Style::default().add_modifier(Modifier::BOLD));
+	// the connections passing the network filter and the search in the
+	// chosen order, `index` points here
+	fn visible(&self) -> Vec<&Connection> {
+		self.arrange(&self.connections)
+	}
 
-	tabs = tabs.select(0);
-	if focus == &Pane::Proxies {
-		tabs = tabs.highlight_style(
-			Style::default()
-				.fg(Color::LightBlue)
-				.add_modifier(Modifier::BOLD),
-		);
+	fn arrange<'a>(
+		&self,
+		connections: &'a [Connection],
+	) -> Vec<&'a Connection> {
+		let mut shown: Vec<_> = connections
+			.iter()
+			.filter(|c| self.accepts(c))
+			.collect();
+		// stable, ties keep the controller's order
+		shown.sort_by(|a, b| {
+			let order = self.sort.compare(a, b);
+			if self.ascending {
+				order
+			} else {
+				order.reverse()
+			}
+		});
+		shown
 	}
 
-	f.render_widget(tabs, chunks[0]);
+	// the cursor stays on its connection if it is still shown
+	fn rearrange(&mut self, change: impl FnOnce(&mut Self)) {
+		let old = self.visible().get(self.index).map(|c| c.id.clone());
+		change(self);
+		let visible = self.visible();
+		self.index = old
+			.and_then(|id| visible.iter().position(|c| c.id == id))
+			.unwrap_or(0);
+		self.group_index = 0;
+	}
 
-	let provider = providers[state.provider];
-	let mut titles: Vec<_> = provider
-		.all
-		.as_ref()
-		.map(|v| v.iter().map(|s| &**s).collect())
-		.unwrap_or_default();
+	fn cycle_network(&mut self) {
+		self.rearrange(|state| state.network = state.network.next());
+	}
 
-	titles.sort();
-	let items: Vec<_> = titles
-		.iter()
-		.skip(state.proxy_index)
-		.enumerate()
-		.map(|(i, &t)| {
-			let mut style = Style::default();
-			if Some(t) == provider.now.as_deref() {
-				style = style
-					.fg(Color::LightRed)
-					.add_modifier(Modifier::BOLD);
+	fn set_filter(&mut self, filter: &str) {
+		self.rearrange(|state| state.filter = filter.to_owned());
+	}
+
+	fn cycle_sort(&mut self) {
+		self.rearrange(|state| state.sort = state.sort.next());
+	}
+
+	fn reverse_sort(&mut self) {
+		self.rearrange(|state| state.ascending = !state.ascending);
+	}
+
+	fn keep_filter(&mut self) {
+		self.kept_filter = self.filter.clone();
+	}
+
+	fn restore_filter(&mut self) {
+		let filter = self.kept_filter.clone();
+		self.set_filter(&filter);
+	}
+
+	// diffs the totals against the previous update
+	fn update_rates(&mut self, connections: &[Connection]) {
+		let now = Instant::now();
+		let elapsed = self.updated.map(|t| now - t.min(now));
+		self.updated = Some(now);
+
+		let previous: HashMap<_, _> =
+			self.connections.iter().map(|c| (&c.id, c)).collect();
+		let mut rates = HashMap::new();
+		for connection in connections {
+			let mut history = self
+				.rates
+				.remove(&connection.id)
+				.unwrap_or_default();
+			let old = previous.get(&connection.id);
+			if let (Some(old), Some(elapsed)) = (old, elapsed) {
+				let millis = elapsed.as_millis().max(1) as u64;
+				let rate = |new: u64, old: u64| {
+					new.saturating_sub(old) * 1000 / millis
+				};
+				history.push_back((
+					rate(connection.upload, old.upload),
+					rate(connection.download, old.download),
+				));
+				if history.len() > RATE_SAMPLES {
+					history.pop_front();
+				}
 			}
-			if i == 0 && focus == &Pane::Proxies {
-				style = style.bg(Color::LightBlue);
+			rates.insert(connection.id.clone(), history);
+		}
+		// closed connections drop their history here
+		self.rates = rates;
+	}
+
+	fn selected(&self) -> Option<&Connection> {
+		match self.grouping {
+			Grouping::None => {
+				self.visible().get(self.index).copied()
 			}
-			ListItem::new(Spans::from(t)).style(style)
-		})
-		.collect();
+			_ => None,
+		}
+	}
 
-	let block = Block::default()
-		.borders(Borders::ALL)
-		.style(Style::default());
-	let list = List::new(items).block(block);
+	// the most connections first
+	fn groups(&self) -> Vec<ConnectionGroup<'_>> {
+		let mut groups: Vec<ConnectionGroup> = Vec::new();
+		for connection in self.visible() {
+			let key = connection.group_key(self.grouping);
+			let group = match groups
+				.iter()
+				.position(|g| g.key == key)
+			{
+				Some(i) => &mut groups[i],
+				_ => {
+					groups.push(ConnectionGroup {
+						key,
+						count: 0,
+						upload: 0,
+						download: 0,
+					});
+					groups.last_mut().unwrap()
+				}
+			};
+			group.count += 1;
+			group.upload += connection.upload;
+			group.download += connection.download;
+		}
+		groups.sort_by(|a, b| {
+			b.count.cmp(&a.count).then_with(|| a.key.cmp(b.key))
+		});
+		groups
+	}
+
+	fn cycle_grouping(&mut self) {
+		self.grouping = self.grouping.next();
+		self.group_index = 0;
+	}
+
+	// the rows the cursor moves over and a mutable handle on it
+	fn cursor(&mut self) -> (usize, &mut usize) {
+		match self.grouping {
+			Grouping::None => {
+				(self.visible().len(), &mut self.index)
+			}
+			_ => (self.groups().len(), &mut self.group_index),
+		}
+	}
+
+	fn next(&mut self) {
+		let (len, index) = self.cursor();
+		if len != 0 {
+			*index = (*index + 1) % len;
+		}
+	}
+
+	fn previous(&mut self) {
+		let (len, index) = self.cursor();
+		if len != 0 {
+			*index = (*index + len - 1) % len;
+		}
+	}
+}
+
+// the selected connection if it survived, otherwise the closest of its old
+// neighbours that did
+fn reselect(old: &[&Connection], index: usize, new: &[&Connection]) -> usize {
+	if new.is_empty() {
+		return 0;
+	}
+	let position = |id: &str| new.iter().position(|c| c.id == id);
+	for distance in 0..old.len() {
+		let before =
+			index.checked_sub(distance).and_then(|i| old.get(i));
+		let after = old.get(index + distance);
+		for connection in before.into_iter().chain(after) {
+			if let Some(i) = position(&connection.id) {
+				return i;
+			}
+		}
+	}
+	index.min(new.len() - 1)
+}
+
+fn format_bytes(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+	let mut value = bytes as f64;
+	let mut unit = 0;
+	// without the second check 1023.96 KB would show as 1024.0 KB
+	while unit < UNITS.len() - 1
+		&& (value >= 1024.0 || unit > 0 && value >= 1023.95)
+	{
+		value /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{} B", bytes)
+	} else {
+		format!("{:.1} {}", value, UNITS[unit])
+	}
+}
+
+fn format_speed(bytes: u64) -> String {
+	format!("{}/s", format_bytes(bytes))
+}
+
+// one line of `/traffic`, bytes per second over the last second
+#[derive(Clone, Copy, Default, Deserialize)]
+struct Traffic {
+	up: u64,
+	down: u64,
+}
+
+// a minute of samples
+const TRAFFIC_SAMPLES: usize = 60;
 
-	f.render_widget(list, chunks[1]);
+#[derive(Default)]
+struct TrafficState {
+	status: StreamStatus,
+	// newest last
+	history: VecDeque<Traffic>,
+}
+
+impl TrafficState {
+	fn push(&mut self, traffic: Traffic) {
+		if self.history.len() == TRAFFIC_SAMPLES {
+			self.history.pop_front();
+		}
+		self.history.push_back(traffic);
+	}
+
+	fn text(&self) -> String {
+		match (self.status, self.history.back()) {
+			(StreamStatus::Unreachable, _) => {
+				String::from("traffic unavailable")
+			}
+			(_, Some(traffic)) => format!(
+				"↑ {}  ↓ {}",
+				format_speed(traffic.up),
+				format_speed(traffic.down)
+			),
+			_ => String::from("↑ -  ↓ -"),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+struct LogEntry {
+	#[serde(rename = "type")]
+	level: String,
+	payload: String,
 }
 
-fn draw_rules<'a>() -> Block<'a> {
-	Block::default().borders(Borders::ALL).title("Rules")
+const LOG_LEVELS: [&str; 4] = ["error", "warning", "info", "debug"];
+// kept entries when the config file does not say
+const LOGS_LEN: usize = 1000;
+
+struct LogsState {
+	status: StreamStatus,
+	entries: VecDeque<LogEntry>,
+	// shown levels, in the order of LOG_LEVELS
+	levels: [bool; 4],
+	// index into LOG_LEVELS of the least severe level shown, the levels
+	// below are hidden on top of those switched off in `levels`
+	min_level: usize,
+	filter: String,
+	// oldest entries are dropped past this
+	cap: usize,
+	// shown entries hidden below the view, 0 when following the newest
+	scroll: usize,
+	// entries shown when last drawn, for paging
+	rows: usize,
 }
 
-fn draw_connections<'a>() -> Block<'a> {
-	Block::default().borders(Borders::ALL).title("Connections")
+impl Default for LogsState {
+	fn default() -> Self {
+		Self {
+			status: StreamStatus::default(),
+			entries: VecDeque::new(),
+			levels: [true; 4],
+			min_level: LOG_LEVELS.len() - 1,
+			filter: String::new(),
+			cap: LOGS_LEN,
+			scroll: 0,
+			rows: 0,
+		}
+	}
 }
 
-fn draw_logs<'a>() -> Block<'a> {
-	Block::default().borders(Borders::ALL).title("Logs")
+impl LogsState {
+	fn push(&mut self, entry: LogEntry) {
+		if self.entries.len() >= self.cap.max(1) {
+			self.entries.pop_front();
+		}
+		// keeps a scrolled back view on the same entries
+		if self.scroll > 0 && self.accepts(&entry) {
+			self.scroll += 1;
+		}
+		self.entries.push_back(entry);
+		self.scroll = self.scroll.min(self.last());
+	}
+
+	fn last(&self) -> usize {
+		self.filtered().count().saturating_sub(1)
+	}
+
+	// towards older entries
+	fn scroll_up(&mut self) {
+		self.scroll = (self.scroll + 1).min(self.last());
+	}
+
+	fn scroll_down(&mut self) {
+		self.scroll = self.scroll.saturating_sub(1);
+	}
+
+	fn toggle_level(&mut self, level: &str) {
+		if let Some(i) = LOG_LEVELS.iter().position(|&l| l == level) {
+			self.levels[i] = !self.levels[i];
+		}
+	}
+
+	// debug, info, warning, error, then debug again
+	fn next_min_level(&mut self) {
+		self.min_level = match self.min_level {
+			0 => LOG_LEVELS.len() - 1,
+			level => level - 1,
+		};
+		self.scroll = self.scroll.min(self.last());
+	}
+
+	fn accepts(&self, entry: &LogEntry) -> bool {
+		// levels we don't know about are never hidden
+		let level = LOG_LEVELS.iter().position(|&l| l == entry.level);
+		if let Some(i) = level {
+			if !self.levels[i] || i > self.min_level {
+				return false;
+			}
+		}
+		self.filter.is_empty()
+			|| entry.payload
+				.to_lowercase()
+				.contains(&self.filter.to_lowercase())
+	}
+
+	fn filtered(&self) -> impl DoubleEndedIterator<Item = &LogEntry> {
+		self.entries.iter().filter(|e| self.accepts(e))
+	}
+
+	fn title(&self) -> String {
+		let mut title = String::from("Logs");
+		match self.status {
+			StreamStatus::Waiting => {
+				title.push_str(" (waiting for controller…)")
+			}
+			StreamStatus::Unreachable => {
+				title.push_str(" (controller unreachable)")
+			}
+			StreamStatus::Connected => {}
+		}
+		if self.min_level < LOG_LEVELS.len() - 1 {
+			title.push_str(&format!(
+				" ({} and up)",
+				LOG_LEVELS[self.min_level]
+			));
+		}
+		if self.levels.contains(&false) {
+			let levels: Vec<_> = LOG_LEVELS
+				.iter()
+				.zip(self.levels)
+				.filter(|(_, shown)| *shown)
+				.map(|(level, _)| *level)
+				.collect();
+			title.push_str(&format!(" [{}]", levels.join(" ")));
+		}
+		if !self.filter.is_empty() {
+			title.push_str(&format!(" \"{}\"", self.filter));
+		}
+		if self.scroll > 0 {
+			title.push_str(&format!(
+				" ({} newer below)",
+				self.scroll
+			));
+		}
+		title
+	}
+}
+
+enum Status {
+	Info(String),
+	Error(String),
+}
+
+// results from background workers, applied on the UI thread
+enum Message {
+	Log(LogEntry),
+	Traffic(Traffic),
+	Stream(Stream, StreamStatus),
+	Status(Status),
+	// 0 when the test timed out or failed, with the test URL reported
+	Delay(String, u16, String),
+	// a comparison round result for the first or second node
+	Compare(usize, u16),
+	// page fetches, with the ticket taken when they were sent if the
+	// page orders them against its mutations
+	Config(u64, Result<Config, String>),
+	Proxies(u64, Result<HashMap<String, Proxy>, String>),
+	Providers(Result<HashMap<String, ProxyProvider>, String>),
+	Rules(Result<Vec<Rule>, String>),
+	Connections(Result<Vec<Connection>, String>),
+	// a provider update or health check returned
+	ProviderDone(String, Status),
+	// the answer to a DNS query, with the name and type asked for
+	Dns(String, String, Result<DnsAnswer, String>),
+	// the controller's version, with the URL to announce as connected
+	Version(Option<String>, Result<Version, String>),
+	// a config patch was answered, with the config fetched after it
+	Patched(u64, ConfigPatch, Result<(), String>, Option<Config>),
+	// a node was selected in a group, or why not, with the proxies
+	// fetched to check
+	Selected(
+		u64,
+		String,
+		String,
+		Option<HashMap<String, Proxy>>,
+		Result<(), String>,
+	),
+	// a change other than a selection was made, the open page is
+	// refetched to show it
+	Changed(Status),
+	// the config and proxies to save as the named profile
+	Snapshot(
+		String,
+		Result<Config, String>,
+		Result<HashMap<String, Proxy>, String>,
+	),
+	// a profile was applied: its name, the mode and selections that
+	// took and what was skipped
+	ProfileApplied(
+		String,
+		Option<Mode>,
+		Vec<(String, String)>,
+		Vec<String>,
+	),
+}
+
+#[derive(Clone, Copy)]
+enum Stream {
+	Logs,
+	Traffic,
+}
+
+impl Stream {
+	fn path(self) -> &'static str {
+		match self {
+			Stream::Logs => "/logs?level=debug",
+			Stream::Traffic => "/traffic",
+		}
+	}
+}
+
+#[derive(Default, Clone, Copy, PartialEq)]
+enum StreamStatus {
+	// still retrying the first connect
+	#[default]
+	Waiting,
+	Connected,
+	// gave up after the initial retry window
+	Unreachable,
+}
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+// how often a stream's tail file is flushed
+const TAIL_FLUSH: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+// Keeps retrying with backoff until the first connect succeeds or `retry`
+// runs out, so starting clashui together with Clash works. Once connected,
+// a dropped stream is reconnected after a fixed delay. Setting `stop` ends
+// the worker at its next line or connect attempt.
+fn spawn_stream<T, F>(
+	http: HttpClient,
+	stream: Stream,
+	retry: Duration,
+	tail: Option<PathBuf>,
+	stop: Arc<AtomicBool>,
+	tx: Sender<Message>,
+	message: F,
+) where
+	T: DeserializeOwned,
+	F: Fn(T) -> Message + Send + 'static,
+{
+	tokio::spawn(async move {
+		let mut tail = tail.and_then(|path| match open_tail(&path) {
+			Ok(file) => Some(file),
+			Err(err) => {
+				tx.send(Message::Status(Status::Error(err)))
+					.ok();
+				None
+			}
+		});
+		let mut flushed = Instant::now();
+		let started = Instant::now();
+		let mut backoff = Duration::from_millis(250);
+		let mut connected = false;
+		loop {
+			if stop.load(Ordering::Relaxed) {
+				return;
+			}
+			let reader = match http.stream(stream.path()).await {
+				Ok(reader) => reader,
+				Err(_) if connected => {
+					time::sleep(RECONNECT_DELAY).await;
+					continue;
+				}
+				Err(_) if started.elapsed() >= retry => {
+					let status = StreamStatus::Unreachable;
+					tx.send(Message::Stream(
+						stream, status,
+					))
+					.ok();
+					return;
+				}
+				Err(_) => {
+					time::sleep(backoff).await;
+					backoff =
+						(backoff * 2).min(MAX_BACKOFF);
+					continue;
+				}
+			};
+
+			if !connected {
+				connected = true;
+				let status = StreamStatus::Connected;
+				if tx.send(Message::Stream(stream, status))
+					.is_err()
+				{
+					return;
+				}
+			}
+			let mut lines = reader.lines();
+			loop {
+				let line = match lines.next_line().await {
+					Ok(Some(line)) => line,
+					_ => break,
+				};
+				if stop.load(Ordering::Relaxed) {
+					return;
+				}
+				let res = match &mut tail {
+					Some(file) => write_tail(
+						file,
+						&line,
+						&mut flushed,
+					),
+					_ => Ok(()),
+				};
+				// the UI keeps streaming without the file
+				if let Err(err) = res {
+					tail = None;
+					let err = format!(
+						"Stopped writing logs: {}",
+						err
+					);
+					tx.send(Message::Status(
+						Status::Error(err),
+					))
+					.ok();
+				}
+				if let Ok(item) = serde_json::from_str(&line) {
+					if tx.send(message(item)).is_err() {
+						return;
+					}
+				}
+			}
+			time::sleep(RECONNECT_DELAY).await;
+		}
+	});
+}
+
+// flushed at most every TAIL_FLUSH, the stream may be busy
+fn write_tail(
+	file: &mut BufWriter<File>,
+	line: &str,
+	flushed: &mut Instant,
+) -> io::Result<()> {
+	writeln!(file, "{}", line)?;
+	if flushed.elapsed() >= TAIL_FLUSH {
+		file.flush()?;
+		*flushed = Instant::now();
+	}
+	Ok(())
+}
+
+fn open_tail(path: &Path) -> Result<BufWriter<File>, String> {
+	File::options()
+		.create(true)
+		.append(true)
+		.open(path)
+		.map(BufWriter::new)
+		.map_err(|err| {
+			format!("Could not open {}: {}", path.display(), err)
+		})
+}
+
+struct App {
+	http: HttpClient,
+	// None until known, or when the controller could not be reached
+	version: Option<Version>,
+	// only the selectable entries, `page` indexes these
+	routes: Vec<Route>,
+	// section titles, drawn above the route at that index
+	headers: Vec<(usize, String)>,
+	page: usize,
+	focus: Pane,
+	general_state: GeneralState,
+	proxies_state: ProxiesState,
+	providers_state: ProvidersState,
+	rules_state: RulesState,
+	connections_state: ConnectionsState,
+	logs_state: LogsState,
+	traffic_state: TrafficState,
+	store: Store,
+	// gets every key press while open, see process_modal_key
+	modal: Option<Modal>,
+	// the GLOBAL switcher waits for the proxies, see open_global
+	opening_global: bool,
+	// shown in the footer until the next key press
+	status: Option<Status>,
+	// marks state with symbols too, not only with color
+	accessible: bool,
+	theme: Theme,
+	// flashed in the footer until it expires
+	banner: Option<(String, Instant)>,
+	// why the last page fetch failed, until one succeeds
+	fetch_error: Option<String>,
+	// whether panes focused for the first time list their keys
+	hints: bool,
+	// the keys of a pane focused for the first time, gone on the next key
+	// press or once it expires
+	hint: Option<(String, Instant)>,
+	seen: HashSet<Pane>,
+	// the first key of a two key command, the `g` of `gg`
+	pending: Option<char>,
+	// where the menu was last drawn, for clicks
+	menu_area: Rect,
+	// a one line breadcrumb instead of the menu column
+	menu_collapsed: bool,
+	// just the General pane, without the menu
+	mode_only: bool,
+	// flips every tick to show the app is polling, None when disabled
+	pulse: Option<bool>,
+	// whole proxy chains in the Connections table, not just the node
+	full_chains: bool,
+	// opened on the first copy and kept, on X11 the copied text is
+	// served by its owner and lost once it is dropped
+	clipboard: Option<arboard::Clipboard>,
+	// set whenever the screen may be out of date, anything animated has
+	// to set it on each tick to keep moving
+	dirty: bool,
+	tx: Sender<Message>,
+	rx: Receiver<Message>,
+	// how long streams retry their first connect
+	connect_retry: Duration,
+	// between automatic refreshes, None when they are off
+	refresh: Option<Duration>,
+	refreshed: Instant,
+	// pages with a fetch in flight and since when, see render_loading
+	loading: HashMap<Route, Instant>,
+	// frame of LOADING_FRAMES, advanced each tick
+	spinner: usize,
+	// set to end the running streams
+	streams_stop: Arc<AtomicBool>,
+	log_tail: Option<PathBuf>,
+	// milliseconds, adjusted with + and -
+	test_timeout: u16,
+	test_urls: Vec<String>,
+	test_strategy: TestStrategy,
+	// round robin position in `test_urls`
+	next_test_url: usize,
+	// whether a tab that stays selected gets its group tested
+	auto_test: bool,
+	// the tab to test once it stayed selected for AUTO_TEST_DELAY
+	auto_test_tab: Option<(String, Instant)>,
+	// when each group was last tested as a whole, see AUTO_TEST_TTL
+	group_tested: HashMap<String, Instant>,
+}
+
+const BANNER_TIME: Duration = Duration::from_secs(3);
+const HINT_TIME: Duration = Duration::from_secs(5);
+// bindings listed in a hint, the first ones of the pane in KEYMAP
+const HINT_KEYS: usize = 4;
+const TEST_URL: &str = "http://www.gstatic.com/generate_204";
+const TEST_TIMEOUT: u16 = 5000;
+const TEST_TIMEOUT_STEP: u16 = 1000;
+const MAX_TEST_TIMEOUT: u16 = 30000;
+// nodes tested at once when testing a whole group
+const TEST_WORKERS: usize = 4;
+// so flipping through tabs does not test every group on the way
+const AUTO_TEST_DELAY: Duration = Duration::from_secs(1);
+// groups tested this recently are not tested again on entering them
+const AUTO_TEST_TTL: Duration = Duration::from_secs(60);
+
+impl App {
+	fn new(
+		base_url: &str,
+		token: Option<&str>,
+		config: &ConfigFile,
+		store: Store,
+	) -> Result<Self, String> {
+		let (tx, rx) = mpsc::channel();
+		let mut routes = Vec::new();
+		let mut headers = Vec::new();
+		let mut menu = config.menu.clone();
+		// the menu needs at least one route to select
+		if menu.iter().all(|section| section.routes.is_empty()) {
+			menu = default_menu();
+		}
+		for section in menu {
+			headers.push((routes.len(), section.title));
+			routes.extend(section.routes);
+		}
+
+		let timeout = match config.timeout_secs {
+			Some(0) => None,
+			Some(secs) => Some(Duration::from_secs(secs)),
+			_ => Some(REQUEST_TIMEOUT),
+		};
+		let http = HttpClient::new(
+			base_url,
+			config.user_agent.as_deref().unwrap_or(USER_AGENT),
+			token,
+			config.insecure,
+			timeout,
+		)?;
+
+		Ok(Self {
+			version: None,
+			http,
+			routes,
+			headers,
+			page: 0,
+			focus: Pane::Menu,
+			general_state: GeneralState::new(),
+			proxies_state: ProxiesState::new(config),
+			providers_state: ProvidersState::default(),
+			rules_state: RulesState::default(),
+			connections_state: ConnectionsState::default(),
+			logs_state: LogsState {
+				cap: config.log_lines.unwrap_or(LOGS_LEN),
+				..LogsState::default()
+			},
+			traffic_state: TrafficState::default(),
+			store,
+			modal: None,
+			opening_global: false,
+			status: None,
+			accessible: config.accessible,
+			theme: config.theme.clone(),
+			banner: None,
+			fetch_error: None,
+			hints: config.hints.unwrap_or(true),
+			hint: None,
+			pending: None,
+			seen: HashSet::new(),
+			menu_area: Rect::default(),
+			menu_collapsed: false,
+			mode_only: false,
+			full_chains: false,
+			clipboard: None,
+			pulse: config.pulse.unwrap_or(true).then_some(false),
+			dirty: true,
+			tx,
+			rx,
+			connect_retry: Duration::from_secs(
+				config.connect_retry_secs.unwrap_or(60),
+			),
+			refresh: Some(Duration::from_secs(1)),
+			refreshed: Instant::now(),
+			loading: HashMap::new(),
+			spinner: 0,
+			streams_stop: Arc::new(AtomicBool::new(false)),
+			log_tail: None,
+			test_timeout: TEST_TIMEOUT,
+			test_urls: if config.test_urls.is_empty() {
+				vec![TEST_URL.to_owned()]
+			} else {
+				config.test_urls.clone()
+			},
+			test_strategy: config.test_strategy.unwrap_or_default(),
+			next_test_url: 0,
+			auto_test: config.auto_test,
+			auto_test_tab: None,
+			group_tested: HashMap::new(),
+		})
+	}
+
+	// stops the streams of a previous controller, if any
+	fn start_streams(&mut self) {
+		self.streams_stop.store(true, Ordering::Relaxed);
+		self.streams_stop = Arc::new(AtomicBool::new(false));
+		self.logs_state.status = StreamStatus::Waiting;
+		self.traffic_state = TrafficState::default();
+		spawn_stream(
+			self.http.clone(),
+			Stream::Logs,
+			self.connect_retry,
+			self.log_tail.clone(),
+			self.streams_stop.clone(),
+			self.tx.clone(),
+			Message::Log,
+		);
+		spawn_stream(
+			self.http.clone(),
+			Stream::Traffic,
+			self.connect_retry,
+			None,
+			self.streams_stop.clone(),
+			self.tx.clone(),
+			Message::Traffic,
+		);
+	}
+
+	fn reconnect(&mut self, url: &str) {
+		let url = match normalize_url(url) {
+			Ok(url) => url,
+			Err(err) => {
+				self.error(err);
+				return;
+			}
+		};
+
+		self.http = match self.http.with_url(&url) {
+			Ok(http) => http,
+			Err(err) => {
+				self.error(err);
+				return;
+			}
+		};
+		// another controller may well support them
+		self.general_state.failures.clear();
+		self.start_streams();
+		self.fetch_data();
+		self.check_version(Some(url));
+	}
+
+	// `connected` is announced once the controller answers
+	fn check_version(&mut self, connected: Option<String>) {
+		self.spawn_fetch(move |http| async move {
+			Message::Version(connected, http.version().await)
+		});
+	}
+
+	fn handle(&mut self, message: Message) {
+		match message {
+			Message::Log(entry) => self.logs_state.push(entry),
+			Message::Traffic(traffic) => {
+				self.traffic_state.push(traffic)
+			}
+			Message::Stream(Stream::Logs, status) => {
+				self.logs_state.status = status
+			}
+			Message::Stream(Stream::Traffic, status) => {
+				self.traffic_state.status = status
+			}
+			Message::Status(status) => {
+				match &status {
+					Status::Info(text) => {
+						log::info!("status: {}", text)
+					}
+					Status::Error(text) => {
+						log::warn!("status: {}", text)
+					}
+				}
+				self.status = Some(status)
+			}
+			Message::Version(connected, Ok(version)) => {
+				self.version = Some(version);
+				if let Some(url) = connected {
+					self.info(format!(
+						"Connected to {}",
+						url
+					));
+				}
+			}
+			Message::Version(_, Err(err)) => {
+				self.version = None;
+				self.error(err);
+			}
+			Message::Patched(ticket, patch, res, config) => {
+				let res = self
+					.general_state
+					.patched(ticket, patch, res, config);
+				if let Err(err) = res {
+					self.error(err);
+				}
+				self.check_external_changes();
+			}
+			Message::Selected(
+				ticket,
+				group,
+				node,
+				proxies,
+				res,
+			) => {
+				if proxies.is_some() {
+					self.proxies_state
+						.receive(ticket, proxies);
+					// our own change, not one to report
+					if let Some(now) =
+						self.proxies_state.now(&group)
+					{
+						self.proxies_state
+							.known_now
+							.insert(
+								group.clone(),
+								now.to_owned(),
+							);
+					}
+				}
+				match res {
+					Ok(()) => {
+						self.remember(&group, &node);
+						self.info(format!(
+							"{} now uses {}",
+							group, node
+						));
+					}
+					Err(err) => self.error(err),
+				}
+				self.check_external_changes();
+			}
+			Message::Changed(status) => {
+				self.fetch_data();
+				self.handle(Message::Status(status));
+			}
+			Message::Delay(node, delay, url) => self
+				.proxies_state
+				.record_delay(node, delay, url),
+			Message::Dns(name, kind, res) => {
+				if let Some(Modal::Dns(lookup)) =
+					&mut self.modal
+				{
+					if lookup.name == name
+						&& lookup.kind == kind
+					{
+						lookup.result = Some(res);
+					}
+				}
+			}
+			Message::Compare(index, delay) => {
+				if let Some(Modal::Compare(comparison)) =
+					&mut self.modal
+				{
+					comparison.samples[index].push(delay);
+				}
+			}
+			Message::Config(ticket, res) => {
+				self.loading.remove(&Route::General);
+				let config = self.fetched("the config", res);
+				self.general_state.receive(ticket, config);
+				self.check_external_changes();
+			}
+			Message::Proxies(ticket, res) => {
+				self.loading.remove(&Route::Proxies);
+				let proxies = self.fetched("the proxies", res);
+				self.proxies_state.receive(ticket, proxies);
+				if std::mem::take(&mut self.opening_global) {
+					self.show_global();
+				}
+				// GLOBAL is the group in use in global mode
+				if self.route() == Some(&Route::Proxies)
+					&& self.general_state
+						.is_active(&Mode::Global)
+				{
+					self.proxies_state.focus_group(GLOBAL);
+				}
+				self.check_external_changes();
+			}
+			Message::Providers(res) => {
+				self.loading.remove(&Route::Providers);
+				let providers =
+					self.fetched("the providers", res);
+				self.providers_state.receive(providers)
+			}
+			Message::Rules(res) => {
+				self.loading.remove(&Route::Rules);
+				let rules = self.fetched("the rules", res);
+				self.rules_state.receive(rules)
+			}
+			Message::ProviderDone(name, status) => {
+				self.providers_state.busy.remove(&name);
+				self.status = Some(status);
+			}
+			Message::Connections(res) => {
+				self.loading.remove(&Route::Connections);
+				let connections =
+					self.fetched("the connections", res);
+				self.connections_state.receive(connections)
+			}
+			Message::Snapshot(name, config, proxies) => {
+				self.save_profile(&name, config, proxies)
+			}
+			Message::ProfileApplied(name, mode, done, skipped) => {
+				self.profile_applied(&name, mode, done, skipped)
+			}
+		}
+		self.dirty = true;
+	}
+
+	// keeps the latest failure in the footer until a fetch succeeds
+	fn fetched<T>(
+		&mut self,
+		what: &str,
+		res: Result<T, String>,
+	) -> Option<T> {
+		match res {
+			Ok(value) => {
+				self.fetch_error = None;
+				Some(value)
+			}
+			Err(err) => {
+				self.fetch_error = Some(format!(
+					"Could not load {}: {}",
+					what, err
+				));
+				None
+			}
+		}
+	}
+
+	fn enter(&mut self, route: Route) {
+		self.focus = route.pane();
+		self.fetch_data();
+		if self.focus == Pane::Proxies {
+			self.schedule_auto_test();
+		}
+	}
+
+	// number keys past the end of a short menu do nothing
+	fn navigate(&mut self, page: usize) {
+		if page >= self.routes.len() {
+			return;
+		}
+		self.page = page;
+		self.fetch_data();
+	}
+
+	// The focused list's length and cursor, for the moves every list
+	// shares. The logs count from the newest at the bottom, flagged by the
+	// third field.
+	fn cursor(&mut self) -> Option<(usize, &mut usize, bool)> {
+		match self.focus {
+			Pane::Proxies => {
+				let state = &mut self.proxies_state;
+				Some((
+					state.proxies_len,
+					&mut state.proxy_index,
+					false,
+				))
+			}
+			Pane::Providers => {
+				let len = self.providers_state.len();
+				Some((
+					len,
+					&mut self.providers_state.index,
+					false,
+				))
+			}
+			Pane::Rules => {
+				let len = self.rules_state.filtered().len();
+				Some((len, &mut self.rules_state.offset, false))
+			}
+			Pane::Connections => {
+				let (len, index) =
+					self.connections_state.cursor();
+				Some((len, index, false))
+			}
+			Pane::Logs => {
+				let len = self.logs_state.filtered().count();
+				Some((len, &mut self.logs_state.scroll, true))
+			}
+			Pane::Menu | Pane::General => None,
+		}
+	}
+
+	// by `rows` rows of the focused list, stopping at either end
+	fn scroll_by(&mut self, rows: usize, down: bool) {
+		if let Some((len, index, reversed)) = self.cursor() {
+			*index = if down != reversed {
+				(*index + rows).min(len.saturating_sub(1))
+			} else {
+				index.saturating_sub(rows)
+			};
+		}
+	}
+
+	// by a `1 / divisor` of the focused list's height, as last drawn
+	fn page(&mut self, down: bool, divisor: usize) {
+		let rows = match self.focus {
+			Pane::Proxies => {
+				self.proxies_state.list_area.height as usize
+			}
+			Pane::Rules => self.rules_state.rows,
+			Pane::Connections => self.connections_state.rows,
+			Pane::Logs => self.logs_state.rows,
+			_ => return,
+		};
+		self.scroll_by((rows / divisor).max(1), down);
+	}
+
+	// to the first or the last row of the focused list
+	fn jump(&mut self, first: bool) {
+		if let Some((len, index, reversed)) = self.cursor() {
+			*index = if first != reversed {
+				0
+			} else {
+				len.saturating_sub(1)
+			};
+		}
+	}
+
+	fn next_menu(&mut self) {
+		let page = self.page + 1;
+		self.page = page % self.routes.len();
+		self.fetch_data();
+	}
+
+	fn previous_menu(&mut self) {
+		let page = self.page + self.routes.len() - 1;
+		self.page = page % self.routes.len();
+		self.fetch_data();
+	}
+
+	// the results arrive through `handle`
+	fn fetch_data(&mut self) {
+		let route = match self.route() {
+			Some(route) => route,
+			_ => return,
+		};
+		match route {
+			Route::General => {
+				self.fetch_config();
+				// for the active selections below the modes
+				self.fetch_proxies();
+			}
+			Route::Proxies => self.fetch_proxies(),
+			Route::Providers => self.fetch_providers(),
+			Route::Rules => {
+				self.fetch_rules();
+				// used to resolve the matched group to a node
+				self.fetch_proxies();
+			}
+			Route::Connections => self.fetch_connections(),
+			Route::Logs => {}
+		}
+	}
+
+	// runs `fetch` as a task, its result arrives as a message
+	fn spawn_fetch<F, T>(&self, fetch: F)
+	where
+		F: FnOnce(HttpClient) -> T,
+		T: Future<Output = Message> + Send + 'static,
+	{
+		let fetch = fetch(self.http.clone());
+		let tx = self.tx.clone();
+		tokio::spawn(async move {
+			let _ = tx.send(fetch.await);
+		});
+	}
+
+	fn fetch_config(&mut self) {
+		self.loading.insert(Route::General, Instant::now());
+		let ticket = self.general_state.sequence.fetch();
+		self.spawn_fetch(move |http| async move {
+			let res = http
+				.configs()
+				.await
+				.map_err(|e| http.describe(&*e));
+			Message::Config(ticket, res)
+		});
+	}
+
+	fn fetch_proxies(&mut self) {
+		self.loading.insert(Route::Proxies, Instant::now());
+		let ticket = self.proxies_state.sequence.fetch();
+		self.spawn_fetch(move |http| async move {
+			let res = http
+				.proxies()
+				.await
+				.map_err(|e| http.describe(&*e));
+			Message::Proxies(ticket, res)
+		});
+	}
+
+	fn fetch_providers(&mut self) {
+		self.loading.insert(Route::Providers, Instant::now());
+		self.spawn_fetch(|http| async move {
+			let res = http
+				.providers()
+				.await
+				.map_err(|e| http.describe(&*e));
+			Message::Providers(res)
+		});
+	}
+
+	fn fetch_rules(&mut self) {
+		self.rules_state.fetched = Some(Instant::now());
+		self.loading.insert(Route::Rules, Instant::now());
+		self.spawn_fetch(|http| async move {
+			let res = http
+				.rules()
+				.await
+				.map_err(|e| http.describe(&*e));
+			Message::Rules(res)
+		});
+	}
+
+	fn fetch_connections(&mut self) {
+		if self.connections_state.fetching {
+			return;
+		}
+		self.connections_state.fetching = true;
+		self.loading.insert(Route::Connections, Instant::now());
+		self.spawn_fetch(|http| async move {
+			let res = http
+				.connections()
+				.await
+				.map_err(|e| http.describe(&*e));
+			Message::Connections(res)
+		});
+	}
+
+	// whether the open page has been waiting on a fetch for a while
+	fn slow_fetch(&self) -> bool {
+		let since =
+			self.route().and_then(|route| self.loading.get(route));
+		since.is_some_and(|since| since.elapsed() >= LOADING_DELAY)
+	}
+
+	fn check_external_changes(&mut self) {
+		let mut changed = self.proxies_state.changed_externally();
+		if self.general_state.changed_externally() {
+			changed.insert(0, String::from("mode"));
+		}
+		if !changed.is_empty() {
+			let text = format!(
+				"Selection changed externally: {}",
+				changed.join(", ")
+			);
+			self.banner = Some((text, Instant::now()));
+		}
+	}
+
+	fn on_tick(&mut self) {
+		let due = self.auto_test_tab.as_ref().is_some_and(
+			|(_, since)| since.elapsed() >= AUTO_TEST_DELAY,
+		);
+		if due {
+			let group = self
+				.auto_test_tab
+				.take()
+				.map(|(group, _)| group);
+			let selected = self.proxies_state.group_name();
+			if self.focus == Pane::Proxies && selected == group {
+				self.test_group();
+			}
+		}
+
+		let expired = self.banner.as_ref().is_some_and(|(_, shown)| {
+			shown.elapsed() >= BANNER_TIME
+		});
+		if expired {
+			self.banner = None;
+			self.dirty = true;
+		}
+		let expired = self
+			.hint
+			.as_ref()
+			.is_some_and(|(_, shown)| shown.elapsed() >= HINT_TIME);
+		if expired {
+			self.hint = None;
+			self.dirty = true;
+		}
+
+		if self.slow_fetch() {
+			self.spinner += 1;
+			self.dirty = true;
+		}
+
+		// connections churn too quickly to only refresh on navigation
+		let every = match self.refresh {
+			Some(every) => every,
+			_ => return,
+		};
+		if self.route() == Some(&Route::Connections)
+			&& self.refreshed.elapsed() >= every
+		{
+			self.refreshed = Instant::now();
+			self.fetch_connections();
+		}
+		if self.route() == Some(&Route::Rules)
+			&& self.rules_state.stale()
+		{
+			self.fetch_rules();
+		}
+	}
+
+	fn select_proxy(&mut self) {
+		if let Some((group, node)) = self.proxies_state.selection() {
+			self.select(group, node);
+		}
+	}
+
+	// the outcome arrives as Message::Selected
+	fn select(&mut self, group: String, node: String) {
+		let auto = self.proxies_state.auto_kind(&group);
+		self.proxies_state.sequence.mutate();
+		let ticket = self.proxies_state.sequence.fetch();
+		self.spawn_fetch(move |http| async move {
+			let (res, proxies) =
+				select(&http, &group, &node, auto).await;
+			Message::Selected(ticket, group, node, proxies, res)
+		});
+	}
+
+	// sends `patch` unless there is a reason not to, the outcome arrives
+	// as Message::Patched
+	fn patch_config(&mut self, patch: Result<ConfigPatch, String>) {
+		let patch = match patch {
+			Ok(patch) => patch,
+			Err(err) => {
+				self.error(err);
+				return;
+			}
+		};
+		self.general_state.sequence.mutate();
+		let ticket = self.general_state.sequence.fetch();
+		self.spawn_fetch(move |http| async move {
+			let res = http
+				.update_config(&patch)
+				.await
+				.map_err(|err| err.to_string());
+			let config = http.configs().await.ok();
+			Message::Patched(ticket, patch, res, config)
+		});
+	}
+
+	// tests the selected tab's group after a while, if still selected
+	fn schedule_auto_test(&mut self) {
+		let group = self.proxies_state.group_name().filter(|group| {
+			self.group_tested
+				.get(group)
+				.is_none_or(|at| at.elapsed() >= AUTO_TEST_TTL)
+		});
+		if self.auto_test {
+			self.auto_test_tab =
+				group.map(|group| (group, Instant::now()));
+		}
+	}
+
+	fn test_group(&mut self) {
+		let state = &self.proxies_state;
+		let (group, nodes): (_, Vec<_>) =
+			match state.providers().get(state.provider) {
+				Some(provider) => (
+					provider.name.clone(),
+					state.nodes(provider)
+						.into_iter()
+						.map(String::from)
+						.collect(),
+				),
+				_ => return,
+			};
+		self.info(format!(
+			"Testing {} nodes of {} ({}ms timeout)",
+			nodes.len(),
+			group,
+			self.test_timeout
+		));
+		self.group_tested.insert(group.clone(), Instant::now());
+
+		let (http, tx) = (self.http.clone(), self.tx.clone());
+		let timeout = self.test_timeout;
+		let urls = self.next_test_urls();
+		let strategy = self.test_strategy;
+		tokio::spawn(async move {
+			// one URL per node is all the group endpoint can do
+			let delays = match urls.as_slice() {
+				[url] => http
+					.group_delay(&group, timeout, url)
+					.await
+					.ok(),
+				_ => None,
+			};
+			match delays {
+				Some(delays) => {
+					for node in nodes {
+						// nodes that timed out are left out
+						let delay = delays
+							.get(&node)
+							.copied()
+							.unwrap_or(0);
+						let url = urls[0].clone();
+						tx.send(Message::Delay(
+							node, delay, url,
+						))
+						.ok();
+					}
+				}
+				// OSS Clash has no /group, test node by node instead
+				_ => {
+					test_nodes(
+						&http, &tx, nodes, timeout,
+						&urls, strategy,
+					)
+					.await
+				}
+			}
+		});
+	}
+
+	fn test_delay(&mut self) {
+		let node = match self.proxies_state.highlighted() {
+			Some((_, node)) => node.to_owned(),
+			_ => return,
+		};
+		self.info(format!(
+			"Testing {} ({}ms timeout)",
+			node, self.test_timeout
+		));
+
+		let timeout = self.test_timeout;
+		let urls = self.next_test_urls();
+		let strategy = self.test_strategy;
+		self.spawn_fetch(move |http| async move {
+			let (delay, url) =
+				measure(&http, &node, timeout, &urls, strategy)
+					.await;
+			Message::Delay(node, delay, url)
+		});
+	}
+
+	// the URLs the next test should try, just one when rotating
+	fn next_test_urls(&mut self) -> Vec<String> {
+		match self.test_strategy {
+			TestStrategy::RoundRobin => {
+				let index = self.next_test_url
+					% self.test_urls.len();
+				self.next_test_url = index + 1;
+				vec![self.test_urls[index].clone()]
+			}
+			_ => self.test_urls.clone(),
+		}
+	}
+
+	// the first press marks a node, the second compares the two
+	fn mark_compare(&mut self) {
+		let node = match self.proxies_state.highlighted() {
+			Some((_, node)) => node.to_owned(),
+			_ => return,
+		};
+		let first = match self.proxies_state.marked.take() {
+			Some(first) if first != node => first,
+			Some(_) => return,
+			_ => {
+				self.info(format!(
+					"Marked {}, press c on another node to compare",
+					node
+				));
+				self.proxies_state.marked = Some(node);
+				return;
+			}
+		};
+
+		let comparison = Comparison::new([first, node]);
+		let nodes = comparison.nodes.clone();
+		let stop = comparison.stop.clone();
+		let http = self.http.clone();
+		let tx = self.tx.clone();
+		let timeout = self.test_timeout;
+		let urls = self.next_test_urls();
+		let strategy = self.test_strategy;
+		tokio::spawn(async move {
+			for _ in 0..COMPARE_ROUNDS {
+				for (index, node) in nodes.iter().enumerate() {
+					let (delay, _) = measure(
+						&http, node, timeout, &urls,
+						strategy,
+					)
+					.await;
+					if stop.load(Ordering::Relaxed) {
+						return;
+					}
+					tx.send(Message::Compare(index, delay))
+						.ok();
+				}
+				time::sleep(COMPARE_INTERVAL).await;
+			}
+		});
+		self.modal = Some(Modal::Compare(comparison));
+	}
+
+	// `name [type]`, A records when no type is given
+	fn query_dns(&mut self, text: &str) {
+		let mut words = text.split_whitespace();
+		let name = match words.next() {
+			Some(name) => name.to_owned(),
+			_ => return,
+		};
+		let kind = words.next().unwrap_or("A").to_ascii_uppercase();
+		let lookup = DnsLookup {
+			name: name.clone(),
+			kind: kind.clone(),
+			result: None,
+		};
+		self.spawn_fetch(move |http| async move {
+			let res = http.dns_query(&name, &kind).await;
+			Message::Dns(name, kind, res)
+		});
+		self.modal = Some(Modal::Dns(lookup));
+	}
+
+	fn clipboard(&mut self) -> Result<&mut arboard::Clipboard, String> {
+		let clipboard = match self.clipboard.take() {
+			Some(clipboard) => clipboard,
+			_ => arboard::Clipboard::new().map_err(|err| {
+				format!("No clipboard available: {}", err)
+			})?,
+		};
+		Ok(self.clipboard.get_or_insert(clipboard))
+	}
+
+	fn copy_node(&mut self) {
+		let node = match self.proxies_state.highlighted() {
+			Some((_, node)) => node.to_owned(),
+			_ => return,
+		};
+		let clipboard = match self.clipboard() {
+			Ok(clipboard) => clipboard,
+			Err(err) => {
+				self.error(err);
+				return;
+			}
+		};
+		match clipboard.set_text(node.as_str()) {
+			Ok(()) => self.info(format!("Copied {}", node)),
+			Err(err) => self.error(format!(
+				"Could not copy {}: {}",
+				node, err
+			)),
+		}
+	}
+
+	fn adjust_test_timeout(&mut self, increase: bool) {
+		self.test_timeout = if increase {
+			self.test_timeout.saturating_add(TEST_TIMEOUT_STEP)
+		} else {
+			self.test_timeout.saturating_sub(TEST_TIMEOUT_STEP)
+		}
+		.clamp(TEST_TIMEOUT_STEP, MAX_TEST_TIMEOUT);
+		self.info(format!("Test timeout: {}ms", self.test_timeout));
+	}
+
+	fn remember(&mut self, group: &str, node: &str) {
+		self.store.push_recent(group, node);
+		self.store.save().ok();
+	}
+
+	// lists GLOBAL's nodes over the General page, the cursor on the one
+	// in use
+	fn open_global(&mut self) {
+		// not fetched with --mode-only, shown once they arrive
+		if self.proxies_state.proxies.is_none() {
+			self.opening_global = true;
+			self.fetch_proxies();
+			return;
+		}
+		self.show_global();
+	}
+
+	fn show_global(&mut self) {
+		let nodes = self.proxies_state.global_nodes();
+		if nodes.is_empty() {
+			let err = match self.proxies_state.proxies {
+				Some(_) => "The controller has no GLOBAL group",
+				_ => "Could not load the proxies",
+			};
+			self.error(String::from(err));
+			return;
+		}
+		let now = self.proxies_state.now(GLOBAL);
+		let index = nodes
+			.iter()
+			.position(|node| Some(*node) == now)
+			.unwrap_or(0);
+		self.modal = Some(Modal::Global(index));
+	}
+
+	fn select_global(&mut self, index: usize) {
+		let node = match self.proxies_state.global_nodes().get(index) {
+			Some(node) => node.to_string(),
+			_ => return,
+		};
+		self.select(GLOBAL.to_owned(), node);
+	}
+
+	fn apply_recent(&mut self, index: usize) {
+		let recent = match self.store.recent.get(index) {
+			Some(recent) => recent.clone(),
+			_ => return,
+		};
+		self.select(recent.group, recent.node);
+	}
+
+	// applied while typing, for the prompts that filter as they go
+	fn preview_prompt(&mut self, prompt: Prompt, text: &str) {
+		match prompt {
+			Prompt::NodeFilter => {
+				self.proxies_state.set_filter(text)
+			}
+			Prompt::ConnectionFilter => {
+				self.connections_state.set_filter(text)
+			}
+			_ => {}
+		}
+	}
+
+	fn cancel_prompt(&mut self, prompt: Prompt) {
+		match prompt {
+			Prompt::NodeFilter => {
+				self.proxies_state.restore_filter()
+			}
+			Prompt::ConnectionFilter => {
+				self.connections_state.restore_filter()
+			}
+			_ => {}
+		}
+	}
+
+	fn submit_prompt(&mut self, prompt: Prompt, text: String) {
+		match prompt {
+			Prompt::NodeFilter => {
+				self.proxies_state.set_filter(&text);
+				self.proxies_state.keep_filter();
+			}
+			Prompt::ConnectionFilter => {
+				self.connections_state.set_filter(&text);
+				self.connections_state.keep_filter();
+			}
+			Prompt::MatchHost => {
+				self.rules_state.host = text.trim().to_owned()
+			}
+			Prompt::ExportRules => self.export_rules(text.trim()),
+			Prompt::LogFilter => self.logs_state.filter = text,
+			Prompt::Controller => self.reconnect(&text),
+			Prompt::SaveProfile => self.snapshot(text.trim()),
+			Prompt::DnsQuery => self.query_dns(&text),
+			Prompt::ReloadConfig => self.reload_config(text.trim()),
+		}
+	}
+
+	// fetches what the profile is made of, saved when it arrives
+	fn snapshot(&mut self, name: &str) {
+		if name.is_empty() {
+			return;
+		}
+		let name = name.to_owned();
+		self.spawn_fetch(move |http| async move {
+			let config = http
+				.configs()
+				.await
+				.map_err(|e| http.describe(&*e));
+			let proxies = http
+				.proxies()
+				.await
+				.map_err(|e| http.describe(&*e));
+			Message::Snapshot(name, config, proxies)
+		});
+	}
+
+	fn save_profile(
+		&mut self,
+		name: &str,
+		config: Result<Config, String>,
+		proxies: Result<HashMap<String, Proxy>, String>,
+	) {
+		let proxies = match proxies {
+			Ok(proxies) => proxies,
+			Err(err) => {
+				self.error(format!(
+					"Could not load the proxies: {}",
+					err
+				));
+				return;
+			}
+		};
+		// only selectors can be switched, the other groups pick for
+		// themselves
+		let selections = proxies
+			.into_values()
+			.filter(|p| p.is_provider())
+			.filter(|p| p.is_selector())
+			.filter_map(|p| Some((p.name, p.now?)))
+			.collect();
+		let profile = Profile {
+			name: name.to_owned(),
+			mode: config.ok().map(|c| c.mode.to_string()),
+			selections,
+		};
+		self.store.save_profile(profile);
+		match self.store.save() {
+			Ok(()) => self.info(format!("Saved profile {}", name)),
+			Err(err) => self.error(format!(
+				"Could not save profile: {}",
+				err
+			)),
+		}
+	}
+
+	// sets the mode and every selection of the profile, skipping groups
+	// and nodes that are gone
+	fn apply_profile(&mut self, index: usize) {
+		let profile = match self.store.profiles.get(index) {
+			Some(profile) => profile.clone(),
+			_ => return,
+		};
+		self.general_state.sequence.mutate();
+		self.proxies_state.sequence.mutate();
+		self.spawn_fetch(move |http| async move {
+			let proxies = http.proxies().await.ok();
+			let mut skipped = Vec::new();
+
+			let mut mode = None;
+			if let Some(wanted) = profile.mode {
+				let wanted = Mode::from(wanted);
+				let patch = ConfigPatch {
+					mode: Some(wanted.clone()),
+					..ConfigPatch::default()
+				};
+				match http.update_config(&patch).await {
+					Ok(()) => mode = Some(wanted),
+					Err(_) => skipped
+						.push(String::from("mode")),
+				}
+			}
+			let mut applied = Vec::new();
+			for (group, node) in profile.selections {
+				let exists = proxies
+					.as_ref()
+					.and_then(|p| p.get(&group))
+					.and_then(|p| p.all.as_ref())
+					.is_some_and(|all| all.contains(&node));
+				if exists
+					&& http.update_proxy(&group, &node)
+						.await
+						.is_ok_and(|(status, _)| {
+							status.is_success()
+						}) {
+					applied.push((group, node));
+				} else {
+					skipped.push(format!(
+						"{} → {}",
+						group, node
+					));
+				}
+			}
+			Message::ProfileApplied(
+				profile.name,
+				mode,
+				applied,
+				skipped,
+			)
+		});
+	}
+
+	fn profile_applied(
+		&mut self,
+		name: &str,
+		mode: Option<Mode>,
+		applied: Vec<(String, String)>,
+		skipped: Vec<String>,
+	) {
+		// our own changes, not ones to report
+		if mode.is_some() {
+			self.general_state.known_mode = mode;
+		}
+		self.proxies_state.known_now.extend(applied);
+		// fetches sent while the profile was applied are stale
+		self.general_state.sequence.mutate();
+		self.proxies_state.sequence.mutate();
+		self.fetch_config();
+		self.fetch_proxies();
+		if skipped.is_empty() {
+			self.info(format!("Applied profile {}", name));
+		} else {
+			self.error(format!(
+				"Applied profile {}, skipped {}",
+				name,
+				skipped.join(", ")
+			));
+		}
+	}
+
+	fn export_rules(&mut self, path: &str) {
+		let rules =
+			self.rules_state.rules.as_deref().unwrap_or_default();
+		match export_rules(rules, &expand_home(path)) {
+			Ok(len) => self.info(format!(
+				"Exported {} rules to {}",
+				len, path
+			)),
+			Err(err) => self.error(format!(
+				"Could not export rules: {}",
+				err
+			)),
+		}
+	}
+
+	// once per pane and session
+	fn show_hint(&mut self) {
+		if self.hints && self.seen.insert(self.focus) {
+			self.hint =
+				Some((key_hint(self.focus), Instant::now()));
+		}
+	}
+
+	fn close_connection(&mut self) {
+		let (id, host) = match self.connections_state.selected() {
+			Some(c) => (c.id.clone(), c.host().to_owned()),
+			_ => return,
+		};
+		self.spawn_fetch(move |http| async move {
+			let status = match http.close_connection(&id).await {
+				Ok(()) => {
+					Status::Info(format!("Closed {}", host))
+				}
+				Err(err) => Status::Error(format!(
+					"Could not close {}: {}",
+					host, err
+				)),
+			};
+			Message::Changed(status)
+		});
+	}
+
+	fn reload_config(&mut self, path: &str) {
+		let path = path.to_owned();
+		self.spawn_fetch(move |http| async move {
+			let status = match http.reload_config(&path).await {
+				Ok(()) if path.is_empty() => Status::Info(
+					String::from("Reloaded the config"),
+				),
+				Ok(()) => Status::Info(format!(
+					"Reloaded the config from {}",
+					path
+				)),
+				Err(err) => Status::Error(format!(
+					"Could not reload the config: {}",
+					err
+				)),
+			};
+			Message::Changed(status)
+		});
+	}
+
+	fn run(&mut self, action: Action) {
+		match action {
+			Action::CloseConnections => self.close_connections(),
+		}
+	}
+
+	fn close_connections(&mut self) {
+		self.spawn_fetch(|http| async move {
+			let status = match http.close_connections().await {
+				Ok(()) => Status::Info(String::from(
+					"Closed all connections",
+				)),
+				Err(err) => Status::Error(format!(
+					"Could not close the connections: {}",
+					err
+				)),
+			};
+			Message::Changed(status)
+		});
+	}
+
+	// the selected provider unless a request for it is still running
+	fn idle_provider(&mut self, label: &'static str) -> Option<String> {
+		let name = self.providers_state.selected()?.name.clone();
+		if self.providers_state.busy.contains_key(&name) {
+			return None;
+		}
+		self.providers_state.busy.insert(name.clone(), label);
+		Some(name)
+	}
+
+	// slow with many nodes, so the result arrives as a message
+	fn healthcheck_provider(&mut self) {
+		let name = match self.idle_provider("checking…") {
+			Some(name) => name,
+			_ => return,
+		};
+		let http = self.http.clone();
+		let tx = self.tx.clone();
+		tokio::spawn(async move {
+			let status =
+				match http.healthcheck_provider(&name).await {
+					Ok(()) => Status::Info(format!(
+						"Checked {}",
+						name
+					)),
+					Err(err) => Status::Error(format!(
+						"Could not check {}: {}",
+						name, err
+					)),
+				};
+			tx.send(Message::ProviderDone(name, status)).ok();
+			let res = http
+				.providers()
+				.await
+				.map_err(|e| http.describe(&*e));
+			tx.send(Message::Providers(res)).ok();
+		});
+	}
+
+	// the proxies are refetched as well, for the new nodes to show up
+	// in their groups
+	fn update_provider(&mut self) {
+		let name = match self.idle_provider("updating…") {
+			Some(name) => name,
+			_ => return,
+		};
+		let ticket = self.proxies_state.sequence.fetch();
+		let http = self.http.clone();
+		let tx = self.tx.clone();
+		tokio::spawn(async move {
+			let status = match http.update_provider(&name).await {
+				Ok(()) => Status::Info(format!(
+					"Updated {}",
+					name
+				)),
+				Err(err) => Status::Error(format!(
+					"Could not update {}: {}",
+					name, err
+				)),
+			};
+			tx.send(Message::ProviderDone(name, status)).ok();
+			let res = http
+				.providers()
+				.await
+				.map_err(|e| http.describe(&*e));
+			tx.send(Message::Providers(res)).ok();
+			let res = http
+				.proxies()
+				.await
+				.map_err(|e| http.describe(&*e));
+			tx.send(Message::Proxies(ticket, res)).ok();
+		});
+	}
+
+	fn info(&mut self, text: String) {
+		log::info!("status: {}", text);
+		self.status = Some(Status::Info(text));
+	}
+
+	fn error(&mut self, text: String) {
+		log::warn!("status: {}", text);
+		self.status = Some(Status::Error(text));
+	}
+
+	fn route(&self) -> Option<&Route> {
+		self.routes.get(self.page)
+	}
+
+	// `start`, or the page and tab open when last quitting; routes gone
+	// from the menu since leave the first page open
+	fn restore_position(&mut self, start: Option<Route>) {
+		let route = match start {
+			Some(route) => Some(route),
+			_ => {
+				self.proxies_state.restore_group =
+					self.store.last_group.clone();
+				self.store.last_route
+			}
+		};
+		let page = route.and_then(|route| {
+			self.routes.iter().position(|r| *r == route)
+		});
+		if let Some(page) = page {
+			self.page = page;
+		}
+	}
+
+	fn save_position(&mut self) {
+		self.store.last_route = self.route().copied();
+		self.store.last_group = match self.proxies_state.view {
+			ProxiesView::Grouped => self
+				.proxies_state
+				.providers()
+				.get(self.proxies_state.provider)
+				.map(|p| p.name.clone()),
+			// keep the last one known
+			ProxiesView::Flat => self.store.last_group.take(),
+		};
+		self.store.save().ok();
+	}
+}
+
+// `localhost:9090/` to `http://localhost:9090`, the paths are appended to it
+// as they are
+fn normalize_url(text: &str) -> Result<String, String> {
+	let text = text.trim();
+	let url = if text.contains("://") {
+		text.trim_end_matches('/').to_owned()
+	} else {
+		format!("http://{}", text.trim_end_matches('/'))
+	};
+	match reqwest::Url::parse(&url) {
+		Ok(parsed)
+			if ["http", "https"].contains(&parsed.scheme())
+				&& parsed
+					.host_str()
+					.is_some_and(|h| !h.is_empty()) =>
+		{
+			Ok(url)
+		}
+		_ => Err(format!(
+			"Not a controller URL: {}, expected one like \
+			 http://127.0.0.1:9090",
+			text
+		)),
+	}
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let cli = Cli::parse();
+	if let Some(path) = &cli.log_file {
+		if let Err(err) = logging::init(path) {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	}
+	// printed as is, the TOML errors span several lines
+	let mut config = match ConfigFile::load(cli.config.as_deref()) {
+		Ok(config) => config,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+	if cli.user_agent.is_some() {
+		config.user_agent = cli.user_agent;
+	}
+	config.accessible |= cli.accessible;
+	config.auto_test |= cli.auto_test;
+	config.insecure |= cli.insecure;
+	if cli.timeout_secs.is_some() {
+		config.timeout_secs = cli.timeout_secs;
+	}
+	let base_url = cli
+		.base_url
+		.or(config.base_url.take())
+		.unwrap_or_else(|| String::from("http://localhost:9090"));
+	let base_url = match normalize_url(&base_url) {
+		Ok(url) => url,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+	let token = cli.token.or(config.secret.take());
+	// requests run as tasks on the runtime and report back as messages,
+	// the UI stays on this thread
+	let runtime = tokio::runtime::Runtime::new()?;
+	let _runtime = runtime.enter();
+	let app = App::new(&base_url, token.as_deref(), &config, Store::load());
+	let mut app = match app {
+		Ok(app) => app,
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+	if config.insecure {
+		// left on the shell once the UI quits
+		eprintln!("Warning: TLS certificates of the controller are not verified");
+	}
+
+	// a blank dashboard says less than the reason on the shell
+	if !cli.no_preflight {
+		match runtime.block_on(app.http.version()) {
+			Ok(version) => app.version = Some(version),
+			Err(err) => {
+				log::error!("preflight: {}", err);
+				log::logger().flush();
+				eprintln!("{}", err);
+				eprintln!("Pass --no-preflight to open the UI anyway.");
+				std::process::exit(1);
+			}
+		}
+	}
+
+	// a panic on the UI thread would leave the shell in raw mode, with
+	// the message lost on the alternate screen
+	let previous = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		if thread::current().name() == Some("main") {
+			restore_terminal().ok();
+		}
+		previous(info);
+	}));
+	enable_raw_mode()?;
+	let mut stdout = io::stdout();
+	execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+	let backend = CrosstermBackend::new(stdout);
+	let mut terminal = Terminal::new(backend)?;
+
+	let tick_rate = Duration::from_secs(1);
+	app.log_tail = cli.log_tail;
+	app.refresh = (cli.refresh_secs > 0)
+		.then(|| Duration::from_secs(cli.refresh_secs));
+	if cli.mode_only {
+		app.mode_only = true;
+		app.focus = Pane::General;
+		app.fetch_config();
+	} else {
+		app.restore_position(cli.start_page);
+		app.start_streams();
+		app.fetch_data();
+	}
+	if cli.no_preflight {
+		app.check_version(None);
+	}
+	app.show_hint();
+	let res = run_app(&mut terminal, &mut app, tick_rate);
+
+	restore_terminal()?;
+
+	if let Err(err) = res {
+		log::error!("{:?}", err);
+		println!("{:?}", err)
+	}
+	if !app.mode_only {
+		app.save_position();
+	}
+	if cli.print_summary_on_exit {
+		// what the controller ended up with, not the last frame
+		let http = app.http.clone();
+		let (config, proxies) = runtime.block_on(async {
+			(http.configs().await.ok(), http.proxies().await.ok())
+		});
+		let ticket = app.general_state.sequence.fetch();
+		app.general_state.receive(ticket, config);
+		let ticket = app.proxies_state.sequence.fetch();
+		app.proxies_state.receive(ticket, proxies);
+		print!("{}", summary(&app.general_state, &app.proxies_state));
+	}
+	log::info!("quit");
+	log::logger().flush();
+
+	Ok(())
+}
+
+// undoes the setup in main, on quitting and from the panic hook
+fn restore_terminal() -> io::Result<()> {
+	disable_raw_mode()?;
+	execute!(
+		io::stdout(),
+		LeaveAlternateScreen,
+		DisableMouseCapture,
+		cursor::Show
+	)
+}
+
+// redraw at least this often even when nothing is known to have changed
+const HEARTBEAT: Duration = Duration::from_secs(5);
+
+fn run_app<B: Backend>(
+	terminal: &mut Terminal<B>,
+	app: &mut App,
+	tick_rate: Duration,
+) -> io::Result<()> {
+	let mut last_tick = Instant::now();
+	let mut last_draw = Instant::now();
+	loop {
+		if app.dirty || last_draw.elapsed() >= HEARTBEAT {
+			terminal.draw(|f| render(f, app))?;
+			app.dirty = false;
+			last_draw = Instant::now();
+		}
+
+		let timeout = tick_rate
+			.checked_sub(last_tick.elapsed())
+			.unwrap_or_else(|| Duration::from_secs(0));
+
+		if event::poll(timeout)? {
+			match event::read()? {
+				Event::Key(key) => {
+					app.dirty = true;
+					let res = process_key_event(key, app);
+					match res {
+						ProcessResult::Noop => {}
+						ProcessResult::Ok => {
+							return Ok(())
+						}
+					}
+				}
+				Event::Mouse(mouse) => {
+					app.dirty = true;
+					process_mouse(mouse, app);
+				}
+				// drawn right away, clicks and the grid keys go by
+				// where the nodes were last drawn
+				Event::Resize(..) => {
+					terminal.autoresize()?;
+					terminal.draw(|f| render(f, app))?;
+					app.dirty = false;
+					last_draw = Instant::now();
+				}
+				_ => {}
+			}
+		}
+
+		while let Ok(message) = app.rx.try_recv() {
+			app.handle(message);
+		}
+
+		if last_tick.elapsed() >= tick_rate {
+			last_tick = Instant::now();
+			if let Some(pulse) = &mut app.pulse {
+				*pulse = !*pulse;
+				app.dirty = true;
+			}
+			app.on_tick();
+		}
+	}
+}
+
+enum ProcessResult {
+	Noop,
+	Ok,
+	// Error,
+}
+
+enum Modal {
+	Prompt(Prompt, String),
+	// cursor into the recent selections
+	Recent(usize),
+	// cursor into the saved profiles
+	Profiles(usize),
+	// cursor into the GLOBAL group's nodes
+	Global(usize),
+	Compare(Comparison),
+	Dns(DnsLookup),
+	// run on `y`, any other key cancels
+	Confirm(Action),
+}
+
+// destructive actions, asked about before they run
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+	CloseConnections,
+}
+
+impl Action {
+	fn question(self) -> &'static str {
+		match self {
+			Action::CloseConnections => "Close all connections?",
+		}
+	}
+}
+
+// a query sent from the DNS prompt, answered or still waiting
+struct DnsLookup {
+	name: String,
+	kind: String,
+	result: Option<Result<DnsAnswer, String>>,
+}
+
+// Sets the group's node and checks the refetched `now` agrees, trying the
+// request a second time before giving up. `auto` is the type of a group
+// that picks its node itself. The proxies of the last check come back as
+// well, None when none could be fetched.
+async fn select(
+	http: &HttpClient,
+	group: &str,
+	name: &str,
+	auto: Option<String>,
+) -> (Result<(), String>, Option<HashMap<String, Proxy>>) {
+	let now = |proxies: &Option<HashMap<String, Proxy>>| {
+		proxies.as_ref()?.get(group)?.now.clone()
+	};
+	let mut proxies = None;
+	for _ in 0..2 {
+		let err = match http.update_proxy(group, name).await {
+			Ok((status, _)) if status.is_success() => None,
+			// some cores let url-test groups be pinned, the others
+			// answer 400
+			Ok((StatusCode::BAD_REQUEST, reason))
+				if auto.is_some() =>
+			{
+				Some(format!(
+					"Cannot select on {} group {}: {}",
+					auto.as_deref().unwrap_or_default(),
+					group,
+					reason
+				))
+			}
+			Ok((_, reason)) => Some(format!(
+				"Could not select {} in {}: {}",
+				name, group, reason
+			)),
+			Err(err) => Some(format!(
+				"Could not select {} in {}: {}",
+				name, group, err
+			)),
+		};
+		if let Some(err) = err {
+			return (Err(err), proxies);
+		}
+		proxies = http.proxies().await.ok();
+		if now(&proxies).as_deref() == Some(name) {
+			return (Ok(()), proxies);
+		}
+	}
+	let err = match now(&proxies) {
+		Some(now) => format!(
+			"{} did not switch to {}, still on {}",
+			group, name, now
+		),
+		_ => format!("Could not select {} in {}", name, group),
+	};
+	(Err(err), proxies)
+}
+
+// tests `node` against each of `urls` and picks one result by `strategy`,
+// a delay of 0 means every URL timed out
+async fn measure(
+	http: &HttpClient,
+	node: &str,
+	timeout: u16,
+	urls: &[String],
+	strategy: TestStrategy,
+) -> (u16, String) {
+	let mut results = Vec::new();
+	for url in urls {
+		let delay =
+			http.proxy_delay(node, timeout, url).await.unwrap_or(0);
+		if delay != 0 {
+			results.push((delay, url));
+		}
+	}
+	results.sort();
+	let result = match strategy {
+		TestStrategy::Median => results.get(results.len() / 2),
+		_ => results.first(),
+	};
+	match result {
+		Some((delay, url)) => (*delay, url.to_string()),
+		_ => (0, urls.first().cloned().unwrap_or_default()),
+	}
+}
+
+// measures `nodes` TEST_WORKERS at a time, sending each result as it
+// comes in
+async fn test_nodes(
+	http: &HttpClient,
+	tx: &Sender<Message>,
+	nodes: Vec<String>,
+	timeout: u16,
+	urls: &[String],
+	strategy: TestStrategy,
+) {
+	stream::iter(nodes)
+		.for_each_concurrent(TEST_WORKERS, |node| async move {
+			let (delay, url) =
+				measure(http, &node, timeout, urls, strategy)
+					.await;
+			tx.send(Message::Delay(node, delay, url)).ok();
+		})
+		.await;
+}
+
+const COMPARE_ROUNDS: usize = 10;
+const COMPARE_INTERVAL: Duration = Duration::from_secs(1);
+
+// two nodes delay tested round after round by a worker
+struct Comparison {
+	nodes: [String; 2],
+	// one delay per finished round, 0 when timed out
+	samples: [Vec<u16>; 2],
+	// tells the worker the view was closed
+	stop: Arc<AtomicBool>,
+}
+
+impl Comparison {
+	fn new(nodes: [String; 2]) -> Self {
+		Self {
+			nodes,
+			samples: Default::default(),
+			stop: Arc::new(AtomicBool::new(false)),
+		}
+	}
+}
+
+impl Drop for Comparison {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+	}
+}
+
+// min, average and max of the rounds that did not time out
+fn delay_stats(samples: &[u16]) -> Option<(u16, u16, u16)> {
+	let delays: Vec<_> = samples.iter().filter(|d| **d != 0).collect();
+	let min = **delays.iter().min()?;
+	let max = **delays.iter().max()?;
+	let sum: u32 = delays.iter().map(|d| **d as u32).sum();
+	Some((min, (sum / delays.len() as u32) as u16, max))
+}
+
+#[derive(Clone, Copy)]
+enum Prompt {
+	NodeFilter,
+	ConnectionFilter,
+	MatchHost,
+	ExportRules,
+	LogFilter,
+	Controller,
+	SaveProfile,
+	DnsQuery,
+	ReloadConfig,
+}
+
+impl Prompt {
+	fn label(self) -> &'static str {
+		match self {
+			Prompt::NodeFilter => "Search nodes: ",
+			Prompt::ConnectionFilter => "Search connections (host or IP): ",
+			Prompt::MatchHost => "Match host: ",
+			Prompt::ExportRules => {
+				"Export rules to (.csv or .yaml): "
+			}
+			Prompt::LogFilter => "Filter logs: ",
+			Prompt::Controller => "Controller URL: ",
+			Prompt::SaveProfile => "Save profile as: ",
+			Prompt::DnsQuery => "DNS query (name [type]): ",
+			Prompt::ReloadConfig => {
+				"Reload config from (empty for the current file): "
+			}
+		}
+	}
+}
+
+// returns the modal to keep open, if any
+fn process_modal_key(
+	code: KeyCode,
+	app: &mut App,
+	modal: Modal,
+) -> Option<Modal> {
+	match modal {
+		Modal::Prompt(prompt, mut text) => match code {
+			KeyCode::Esc => {
+				app.cancel_prompt(prompt);
+				None
+			}
+			KeyCode::Enter => {
+				app.submit_prompt(prompt, text);
+				// the result of some prompts opens in a modal
+				app.modal.take()
+			}
+			_ => {
+				edit_line(&mut text, code);
+				app.preview_prompt(prompt, &text);
+				Some(Modal::Prompt(prompt, text))
+			}
+		},
+		Modal::Recent(index) => {
+			let len = app.store.recent.len().max(1);
+			match code {
+				KeyCode::Esc | KeyCode::Char('R') => None,
+				KeyCode::Char('j') => {
+					Some(Modal::Recent((index + 1) % len))
+				}
+				KeyCode::Char('k') => Some(Modal::Recent(
+					(index + len - 1) % len,
+				)),
+				KeyCode::Char(' ') | KeyCode::Enter => {
+					app.apply_recent(index);
+					None
+				}
+				_ => Some(Modal::Recent(index)),
+			}
+		}
+		Modal::Profiles(index) => {
+			let len = app.store.profiles.len().max(1);
+			match code {
+				KeyCode::Esc | KeyCode::Char('P') => None,
+				KeyCode::Char('j') => {
+					Some(Modal::Profiles((index + 1) % len))
+				}
+				KeyCode::Char('k') => Some(Modal::Profiles(
+					(index + len - 1) % len,
+				)),
+				KeyCode::Char(' ') | KeyCode::Enter => {
+					app.apply_profile(index);
+					None
+				}
+				_ => Some(Modal::Profiles(index)),
+			}
+		}
+		Modal::Global(index) => {
+			let len = app.proxies_state.global_nodes().len().max(1);
+			match code {
+				KeyCode::Esc | KeyCode::Char('p') => None,
+				KeyCode::Char('j') => {
+					Some(Modal::Global((index + 1) % len))
+				}
+				KeyCode::Char('k') => Some(Modal::Global(
+					(index + len - 1) % len,
+				)),
+				KeyCode::Char(' ') | KeyCode::Enter => {
+					app.select_global(index);
+					None
+				}
+				_ => Some(Modal::Global(index)),
+			}
+		}
+		Modal::Compare(comparison) => match code {
+			KeyCode::Esc => None,
+			_ => Some(Modal::Compare(comparison)),
+		},
+		Modal::Dns(lookup) => match code {
+			KeyCode::Esc | KeyCode::Enter => None,
+			_ => Some(Modal::Dns(lookup)),
+		},
+		Modal::Confirm(action) => {
+			if code == KeyCode::Char('y') {
+				app.run(action);
+			}
+			None
+		}
+	}
+}
+
+// clicks on the menu and the node list, the wheel moves their cursors
+fn process_mouse(mouse: MouseEvent, app: &mut App) {
+	if app.modal.is_some() {
+		return;
+	}
+	let (x, y) = (mouse.column, mouse.row);
+	let menu = app.menu_area.inner(&Margin {
+		vertical: 1,
+		horizontal: 1,
+	});
+	let on_menu = inside(menu, x, y);
+	let on_nodes = app.route() == Some(&Route::Proxies)
+		&& inside(app.proxies_state.list_area, x, y);
+
+	match mouse.kind {
+		MouseEventKind::Down(MouseButton::Left) => {
+			app.status = None;
+			if on_menu {
+				let rows = menu_rows(
+					app.routes.len(),
+					&app.headers,
+				);
+				let row = (y - menu.y) as usize;
+				if let Some(Some(page)) = rows.get(row) {
+					app.focus = Pane::Menu;
+					app.navigate(*page);
+				}
+			} else if let Some(index) = on_nodes
+				.then(|| app.proxies_state.node_at(x, y))
+				.flatten()
+			{
+				app.focus = Pane::Proxies;
+				app.proxies_state.proxy_index = index;
+				app.select_proxy();
+			}
+		}
+		MouseEventKind::ScrollDown if on_menu => app.next_menu(),
+		MouseEventKind::ScrollUp if on_menu => app.previous_menu(),
+		MouseEventKind::ScrollDown if on_nodes => {
+			app.proxies_state.next_proxy()
+		}
+		MouseEventKind::ScrollUp if on_nodes => {
+			app.proxies_state.previous_proxy()
+		}
+		_ => {}
+	}
+}
+
+// Ctrl-d and Ctrl-u page by half a list, other combinations act as their
+// key alone
+fn process_key_event(key: KeyEvent, app: &mut App) -> ProcessResult {
+	let control = key.modifiers.contains(KeyModifiers::CONTROL);
+	log::debug!(
+		"key {:?} {:?} on {:?}{}",
+		key.modifiers,
+		key.code,
+		app.focus,
+		if app.modal.is_some() {
+			" under a modal"
+		} else {
+			""
+		}
+	);
+	match key.code {
+		KeyCode::Char(c @ ('d' | 'u'))
+			if control && app.modal.is_none() =>
+		{
+			log::debug!(
+				"half page {}",
+				if c == 'd' { "down" } else { "up" }
+			);
+			app.status = None;
+			app.pending = None;
+			app.page(c == 'd', 2);
+			ProcessResult::Noop
+		}
+		code => process_key(code, app),
+	}
+}
+
+fn process_key(code: KeyCode, app: &mut App) -> ProcessResult {
+	app.status = None;
+	app.hint = None;
+
+	// keys never leak through an open modal to the panes below it
+	if let Some(modal) = app.modal.take() {
+		app.modal = process_modal_key(code, app, modal);
+		return ProcessResult::Noop;
+	}
+
+	if let KeyCode::Char('q') = code {
+		log::debug!("quit with q");
+		return ProcessResult::Ok;
+	}
+	if let KeyCode::Char('r') = code {
+		log::debug!("reload {:?}", app.route());
+		app.fetch_data();
+		return ProcessResult::Noop;
+	}
+	if let KeyCode::Char('z') = code {
+		app.menu_collapsed = !app.menu_collapsed;
+		return ProcessResult::Noop;
+	}
+	if app.mode_only && matches!(code, KeyCode::Esc | KeyCode::Char('h')) {
+		log::debug!("quit the mode selector");
+		return ProcessResult::Ok;
+	}
+
+	// gg and G in every list, and paging in those that know their height
+	let pending = app.pending.take();
+	if let KeyCode::PageDown | KeyCode::PageUp = code {
+		app.page(code == KeyCode::PageDown, 1);
+		return ProcessResult::Noop;
+	}
+	if let KeyCode::Char(key @ ('g' | 'G')) = code {
+		if app.cursor().is_some() {
+			match (key, pending) {
+				('g', Some('g')) => app.jump(true),
+				('g', _) => app.pending = Some('g'),
+				_ => app.jump(false),
+			}
+			return ProcessResult::Noop;
+		}
+	}
+
+	let focus = &app.focus;
+	match focus {
+		Pane::Menu => match code {
+			KeyCode::Char('j') => app.next_menu(),
+			KeyCode::Char('k') => app.previous_menu(),
+			KeyCode::Char('l') => {
+				if let Some(route) = app.route().copied() {
+					app.enter(route);
+				}
+			}
+			KeyCode::Char('1') => app.navigate(0),
+			KeyCode::Char('2') => app.navigate(1),
+			KeyCode::Char('3') => app.navigate(2),
+			KeyCode::Char('4') => app.navigate(3),
+			KeyCode::Char('5') => app.navigate(4),
+			KeyCode::Char('6') => app.navigate(5),
+			KeyCode::Char('R') => {
+				app.modal = Some(Modal::Recent(0))
+			}
+			KeyCode::Char('P') => {
+				app.modal = Some(Modal::Profiles(0))
+			}
+			KeyCode::Char('S') => {
+				app.modal = Some(Modal::Prompt(
+					Prompt::SaveProfile,
+					String::new(),
+				))
+			}
+			KeyCode::Char('C') => {
+				let url = app.http.url.clone();
+				app.modal = Some(Modal::Prompt(
+					Prompt::Controller,
+					url,
+				));
+			}
+			KeyCode::Char('D') => {
+				app.modal = Some(Modal::Prompt(
+					Prompt::DnsQuery,
+					String::new(),
+				))
+			}
+			_ => {}
+		},
+		Pane::General => match code {
+			KeyCode::Esc | KeyCode::Char('h') => {
+				app.focus = Pane::Menu;
+			}
+			KeyCode::Char(' ') => {
+				app.patch_config(app.general_state.mode_patch())
+			}
+			KeyCode::Char('j') => {
+				app.general_state.next_mode();
+			}
+			KeyCode::Char('k') => {
+				app.general_state.previous_mode();
+			}
+			KeyCode::Char('a') => app.patch_config(
+				app.general_state.allow_lan_patch(),
+			),
+			KeyCode::Char('L') => app.patch_config(
+				app.general_state.log_level_patch(),
+			),
+			KeyCode::Char('p') => app.open_global(),
+			KeyCode::Char('R') => {
+				app.modal = Some(Modal::Prompt(
+					Prompt::ReloadConfig,
+					String::new(),
+				))
+			}
+			_ => {}
+		},
+		Pane::Proxies => match code {
+			KeyCode::Esc | KeyCode::Char('h') => {
+				app.focus = Pane::Menu;
+			}
+			KeyCode::Char(' ') => {
+				app.select_proxy();
+			}
+			KeyCode::Char('y') => app.copy_node(),
+			KeyCode::Char('j') => {
+				app.proxies_state.next_proxy();
+			}
+			KeyCode::Char('k') => {
+				app.proxies_state.previous_proxy();
+			}
+			KeyCode::Char('H') => {
+				app.proxies_state.previous_tab();
+				app.schedule_auto_test();
+			}
+			KeyCode::Char('L') => {
+				app.proxies_state.next_tab();
+				app.schedule_auto_test();
+			}
+			KeyCode::Char('R') => {
+				app.modal = Some(Modal::Recent(0));
+			}
+			KeyCode::Char('v') => {
+				app.proxies_state.toggle_view();
+			}
+			KeyCode::Char('s') => {
+				app.proxies_state.cycle_sort();
+			}
+			KeyCode::Char('/') => {
+				let text = app.proxies_state.filter.clone();
+				app.modal = Some(Modal::Prompt(
+					Prompt::NodeFilter,
+					text,
+				));
+			}
+			KeyCode::Char('b') => {
+				app.proxies_state.toggle_latency_display();
+			}
+			KeyCode::Char('o') => {
+				app.proxies_state.cycle_tab_order();
+			}
+			KeyCode::Char('.') => {
+				app.proxies_state.toggle_hidden();
+			}
+			KeyCode::Char('t') => app.test_delay(),
+			KeyCode::Char('T') => app.test_group(),
+			KeyCode::Char('c') => app.mark_compare(),
+			KeyCode::Char('w') => {
+				app.proxies_state.toggle_columns();
+			}
+			KeyCode::Char('[') => {
+				app.proxies_state.previous_column();
+			}
+			KeyCode::Char(']') => {
+				app.proxies_state.next_column();
+			}
+			KeyCode::Char('+') | KeyCode::Char('=') => {
+				app.adjust_test_timeout(true);
+			}
+			KeyCode::Char('-') => app.adjust_test_timeout(false),
+			_ => {}
+		},
+		Pane::Providers => match code {
+			KeyCode::Esc | KeyCode::Char('h') => {
+				app.focus = Pane::Menu;
+			}
+			KeyCode::Char('j') => app.providers_state.next(),
+			KeyCode::Char('k') => app.providers_state.previous(),
+			KeyCode::Char('u') => app.update_provider(),
+			KeyCode::Char('t') => app.healthcheck_provider(),
+			_ => {}
+		},
+		Pane::Rules => match code {
+			KeyCode::Esc | KeyCode::Char('h') => {
+				app.focus = Pane::Menu;
+			}
+			KeyCode::Char('j') => app.rules_state.scroll_down(),
+			KeyCode::Char('k') => app.rules_state.scroll_up(),
+			KeyCode::Char('m') => {
+				let text = String::new();
+				app.modal = Some(Modal::Prompt(
+					Prompt::MatchHost,
+					text,
+				));
+			}
+			KeyCode::Char('f') => {
+				app.rules_state.cycle_filter();
+			}
+			KeyCode::Char('e') => {
+				let text = String::from("rules.yaml");
+				app.modal = Some(Modal::Prompt(
+					Prompt::ExportRules,
+					text,
+				));
+			}
+			_ => {}
+		},
+		Pane::Connections => match code {
+			KeyCode::Esc | KeyCode::Char('h') => {
+				app.focus = Pane::Menu;
+			}
+			KeyCode::Char('j') => app.connections_state.next(),
+			KeyCode::Char('k') => app.connections_state.previous(),
+			KeyCode::Char('/') => {
+				let text = app.connections_state.filter.clone();
+				app.modal = Some(Modal::Prompt(
+					Prompt::ConnectionFilter,
+					text,
+				));
+			}
+			KeyCode::Char('b') => {
+				app.connections_state.cycle_grouping()
+			}
+			KeyCode::Char('n') => {
+				app.connections_state.cycle_network()
+			}
+			KeyCode::Char('s') => {
+				app.connections_state.cycle_sort()
+			}
+			KeyCode::Char('S') => {
+				app.connections_state.reverse_sort()
+			}
+			KeyCode::Char('c') => {
+				app.full_chains = !app.full_chains
+			}
+			KeyCode::Char('d') => app.close_connection(),
+			KeyCode::Char('D') => {
+				app.modal = Some(Modal::Confirm(
+					Action::CloseConnections,
+				))
+			}
+			_ => {}
+		},
+		Pane::Logs => match code {
+			KeyCode::Esc | KeyCode::Char('h') => {
+				app.focus = Pane::Menu;
+			}
+			KeyCode::Char('/') => {
+				let text = app.logs_state.filter.clone();
+				app.modal = Some(Modal::Prompt(
+					Prompt::LogFilter,
+					text,
+				));
+			}
+			KeyCode::Char('e') => {
+				app.logs_state.toggle_level("error")
+			}
+			KeyCode::Char('w') => {
+				app.logs_state.toggle_level("warning")
+			}
+			KeyCode::Char('i') => {
+				app.logs_state.toggle_level("info")
+			}
+			KeyCode::Char('d') => {
+				app.logs_state.toggle_level("debug")
+			}
+			KeyCode::Char('m') => app.logs_state.next_min_level(),
+			KeyCode::Char('k') => app.logs_state.scroll_up(),
+			KeyCode::Char('j') => app.logs_state.scroll_down(),
+			_ => {}
+		},
+		// _ => match code {
+		// 	KeyCode::Esc | KeyCode::Char('h') => {
+		// 		app.focus = Pane::Menu;
+		// 	}
+		// 	_ => {}
+		// },
+	}
+	app.show_hint();
+
+	ProcessResult::Noop
+}
+
+// keys and what they do per pane, kept in step with process_key by hand,
+// the most useful first since hints only show the first few
+const KEYMAP: &[(Pane, &str, &str)] = &[
+	(Pane::Menu, "j/k", "move"),
+	(Pane::Menu, "l", "open"),
+	(Pane::Menu, "1-6", "jump"),
+	(Pane::Menu, "q", "quit"),
+	(Pane::Menu, "r", "reload"),
+	(Pane::Menu, "z", "hide menu"),
+	(Pane::Menu, "R", "recent"),
+	(Pane::Menu, "P", "profiles"),
+	(Pane::Menu, "S", "save profile"),
+	(Pane::Menu, "C", "controller"),
+	(Pane::Menu, "D", "dns query"),
+	(Pane::General, "j/k", "move"),
+	(Pane::General, "space", "set mode"),
+	(Pane::General, "h", "back"),
+	(Pane::General, "a", "allow lan"),
+	(Pane::General, "L", "log level"),
+	(Pane::General, "p", "global node"),
+	(Pane::General, "R", "reload config"),
+	(Pane::Proxies, "space", "select"),
+	(Pane::Proxies, "H/L", "tabs"),
+	(Pane::Proxies, "t", "test"),
+	(Pane::Proxies, "T", "test group"),
+	(Pane::Proxies, "s", "sort"),
+	(Pane::Proxies, "/", "search"),
+	(Pane::Proxies, "j/k", "move"),
+	(Pane::Proxies, "v", "view"),
+	(Pane::Proxies, "b", "latency display"),
+	(Pane::Proxies, "o", "tab order"),
+	(Pane::Proxies, ".", "hidden"),
+	(Pane::Proxies, "c", "compare"),
+	(Pane::Proxies, "y", "copy name"),
+	(Pane::Proxies, "w", "columns"),
+	(Pane::Proxies, "[/]", "column"),
+	(Pane::Proxies, "+/-", "test timeout"),
+	(Pane::Proxies, "R", "recent"),
+	(Pane::Proxies, "gg/G", "top/bottom"),
+	(Pane::Proxies, "^d/^u", "half page"),
+	(Pane::Proxies, "h", "back"),
+	(Pane::Providers, "j/k", "move"),
+	(Pane::Providers, "u", "update"),
+	(Pane::Providers, "t", "health check"),
+	(Pane::Providers, "gg/G", "top/bottom"),
+	(Pane::Providers, "h", "back"),
+	(Pane::Rules, "m", "match host"),
+	(Pane::Rules, "f", "filter"),
+	(Pane::Rules, "e", "export"),
+	(Pane::Rules, "j/k", "scroll"),
+	(Pane::Rules, "gg/G", "top/bottom"),
+	(Pane::Rules, "^d/^u", "half page"),
+	(Pane::Rules, "h", "back"),
+	(Pane::Connections, "j/k", "move"),
+	(Pane::Connections, "/", "search"),
+	(Pane::Connections, "b", "group"),
+	(Pane::Connections, "n", "network"),
+	(Pane::Connections, "s/S", "sort"),
+	(Pane::Connections, "c", "chains"),
+	(Pane::Connections, "d", "close"),
+	(Pane::Connections, "D", "close all"),
+	(Pane::Connections, "gg/G", "top/bottom"),
+	(Pane::Connections, "^d/^u", "half page"),
+	(Pane::Connections, "h", "back"),
+	(Pane::Logs, "/", "filter"),
+	(Pane::Logs, "m", "min level"),
+	(Pane::Logs, "e/w/i/d", "levels"),
+	(Pane::Logs, "j/k", "scroll"),
+	(Pane::Logs, "gg/G", "top/bottom"),
+	(Pane::Logs, "^d/^u", "half page"),
+	(Pane::Logs, "h", "back"),
+];
+
+fn key_hint(pane: Pane) -> String {
+	let keys: Vec<_> = KEYMAP
+		.iter()
+		.filter(|(p, ..)| *p == pane)
+		.take(HINT_KEYS)
+		.map(|(_, keys, action)| format!("{} {}", keys, action))
+		.collect();
+	keys.join(", ")
+}
+
+fn edit_line(line: &mut String, code: KeyCode) {
+	match code {
+		KeyCode::Backspace => {
+			line.pop();
+		}
+		KeyCode::Char(c) => line.push(c),
+		_ => {}
+	}
+}
+
+fn render<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+	render_layout(f, app);
+
+	// on the top right border, drawn last to stay on top
+	if let Some(pulse) = app.pulse {
+		let size = f.size();
+		if size.width > 2 {
+			let rect = Rect::new(size.width - 2, 0, 1, 1);
+			let glyph = if pulse { "•" } else { "·" };
+			let style =
+				Style::default().add_modifier(Modifier::DIM);
+			f.render_widget(
+				Paragraph::new(Span::styled(glyph, style)),
+				rect,
+			);
+		}
+	}
+}
+
+fn error_line(text: &str, accessible: bool) -> Spans<'static> {
+	let text = if accessible {
+		format!("! {}", text)
+	} else {
+		text.to_owned()
+	};
+	Spans::from(Span::styled(text, Style::default().fg(Color::Red)))
+}
+
+fn render_layout<B: Backend>(f: &mut Frame<B>, app: &mut App) {
+	let mut area = f.size();
+	let footer = match (&app.modal, &app.status) {
+		(Some(Modal::Prompt(prompt, text)), _) => {
+			Some(Spans::from(vec![
+				Span::styled(
+					prompt.label(),
+					Style::default()
+						.add_modifier(Modifier::BOLD),
+				),
+				Span::raw(text.as_str()),
+				Span::raw("_"),
+			]))
+		}
+		(_, Some(Status::Info(text))) => {
+			Some(Spans::from(text.as_str()))
+		}
+		(_, Some(Status::Error(text))) => {
+			Some(error_line(text, app.accessible))
+		}
+		_ => match (&app.fetch_error, &app.banner, &app.hint) {
+			(Some(text), _, _) => {
+				Some(error_line(text, app.accessible))
+			}
+			(_, Some((text, _)), _) => {
+				Some(Spans::from(Span::styled(
+					text.as_str(),
+					Style::default()
+						.fg(Color::Black)
+						.bg(Color::Yellow)
+						.add_modifier(Modifier::BOLD),
+				)))
+			}
+			(_, _, Some((text, _))) => {
+				Some(Spans::from(Span::styled(
+					text.as_str(),
+					Style::default()
+						.add_modifier(Modifier::DIM),
+				)))
+			}
+			_ => None,
+		},
+	};
+	if let Some(footer) = footer {
+		let rows = Layout::default()
+			.direction(Direction::Vertical)
+			.constraints(
+				[Constraint::Min(0), Constraint::Length(1)]
+					.as_ref(),
+			)
+			.split(area);
+		area = rows[0];
+		f.render_widget(Paragraph::new(footer), rows[1]);
+	}
+
+	if !app.mode_only {
+		let rows = Layout::default()
+			.direction(Direction::Vertical)
+			.constraints(
+				[Constraint::Min(0), Constraint::Length(1)]
+					.as_ref(),
+			)
+			.split(area);
+		area = rows[0];
+		let text = Span::styled(
+			app.traffic_state.text(),
+			Style::default().add_modifier(Modifier::DIM),
+		);
+		f.render_widget(Paragraph::new(text), rows[1]);
+	}
+
+	if app.mode_only {
+		let (focus, accessible) = (&app.focus, app.accessible);
+		render_general(
+			f,
+			&app.theme,
+			&mut app.general_state,
+			&app.proxies_state,
+			focus,
+			accessible,
+			area,
+		);
+		return;
+	}
+
+	let mut title = match &app.version {
+		Some(version) => format!("Clash {}", version),
+		_ => String::from("Clash (disconnected)"),
+	};
+	// kept in sight so it is not forgotten
+	if app.http.insecure {
+		title.push_str(" (TLS unverified)");
+	}
+	if app.menu_collapsed {
+		let rows = Layout::default()
+			.direction(Direction::Vertical)
+			.constraints(
+				[Constraint::Length(1), Constraint::Min(0)]
+					.as_ref(),
+			)
+			.split(area);
+		let crumbs =
+			breadcrumb(&app.routes, &app.headers, app.page, title);
+		f.render_widget(Paragraph::new(crumbs), rows[0]);
+		app.menu_area = Rect::default();
+		render_main(f, app, rows[1]);
+		render_loading(f, app, rows[1]);
+		render_modal(f, app);
+		return;
+	}
+
+	// at most half of the screen, the page gets the rest
+	let width = menu_width(&app.routes, &app.headers, &title)
+		.min(area.width / 2);
+	let chunks = Layout::default()
+		.direction(Direction::Horizontal)
+		.constraints(
+			[Constraint::Length(width), Constraint::Min(0)]
+				.as_ref(),
+		)
+		.split(area);
+
+	let menu = draw_menu(
+		&app.routes,
+		&app.headers,
+		app.page,
+		app.accessible,
+		&app.theme,
+		title,
+	);
+	f.render_widget(menu, chunks[0]);
+	app.menu_area = chunks[0];
+
+	render_main(f, app, chunks[1]);
+	render_loading(f, app, chunks[1]);
+	render_modal(f, app);
+}
+
+// fetches this quick go without a spinner, which would only flicker
+const LOADING_DELAY: Duration = Duration::from_millis(300);
+const LOADING_FRAMES: [&str; 4] = ["◐", "◓", "◑", "◒"];
+
+// on the top border of the page, left of the pulse
+fn render_loading<B: Backend>(f: &mut Frame<B>, app: &App, rect: Rect) {
+	if !app.slow_fetch() {
+		return;
+	}
+	let text = format!(
+		" {} loading ",
+		LOADING_FRAMES[app.spinner % LOADING_FRAMES.len()]
+	);
+	let width = text.chars().count() as u16;
+	if rect.width < width + 4 {
+		return;
+	}
+	let rect = Rect::new(rect.x + rect.width - width - 3, rect.y, width, 1);
+	let style = Style::default().add_modifier(Modifier::DIM);
+	f.render_widget(Paragraph::new(Span::styled(text, style)), rect);
+}
+
+// "3/57" on the bottom border of a list, `index` counting from 0 within
+// everything the list holds rather than what fits on screen
+fn render_position<B: Backend>(
+	f: &mut Frame<B>,
+	rect: Rect,
+	index: usize,
+	len: usize,
+) {
+	if len == 0 {
+		return;
+	}
+	let text = format!(" {}/{} ", index.min(len - 1) + 1, len);
+	let width = text.chars().count() as u16;
+	if rect.width < width + 4 || rect.height < 2 {
+		return;
+	}
+	let rect = Rect::new(
+		rect.x + rect.width - width - 2,
+		rect.y + rect.height - 1,
+		width,
+		1,
+	);
+	f.render_widget(Paragraph::new(text), rect);
+}
+
+fn render_modal<B: Backend>(f: &mut Frame<B>, app: &App) {
+	match &app.modal {
+		Some(Modal::Recent(index)) => render_recent(
+			f,
+			&app.theme,
+			&app.store,
+			*index,
+			app.accessible,
+			f.size(),
+		),
+		Some(Modal::Profiles(index)) => render_profiles(
+			f,
+			&app.theme,
+			&app.store,
+			*index,
+			app.accessible,
+			f.size(),
+		),
+		Some(Modal::Global(index)) => render_global(
+			f,
+			&app.theme,
+			&app.proxies_state,
+			app.general_state.is_active(&Mode::Global),
+			*index,
+			app.accessible,
+			f.size(),
+		),
+		Some(Modal::Compare(comparison)) => {
+			render_comparison(f, &app.theme, comparison, f.size())
+		}
+		Some(Modal::Dns(lookup)) => {
+			render_dns(f, &app.theme, lookup, f.size())
+		}
+		Some(Modal::Confirm(action)) => {
+			render_confirm(f, &app.theme, *action, f.size())
+		}
+		_ => {}
+	}
+}
+
+fn centered_rect(width: u16, height: u16, rect: Rect) -> Rect {
+	let width = width.min(rect.width);
+	let height = height.min(rect.height);
+	Rect::new(
+		rect.x + (rect.width - width) / 2,
+		rect.y + (rect.height - height) / 2,
+		width,
+		height,
+	)
+}
+
+fn render_recent<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	store: &Store,
+	index: usize,
+	accessible: bool,
+	rect: Rect,
+) {
+	let mut items: Vec<_> = store
+		.recent
+		.iter()
+		.enumerate()
+		.map(|(i, recent)| {
+			let mut style = Style::default();
+			if i == index {
+				style = style.bg(theme.highlight);
+			}
+			let spans = Spans::from(vec![
+				marker(accessible, i == index, false),
+				Span::styled(
+					recent.group.as_str(),
+					Style::default()
+						.add_modifier(Modifier::DIM),
+				),
+				Span::raw(" → "),
+				Span::raw(recent.node.as_str()),
+			]);
+			ListItem::new(spans).style(style)
+		})
+		.collect();
+	if items.is_empty() {
+		items.push(ListItem::new("No recent selections"));
+	}
+
+	let rect = centered_rect(50, items.len() as u16 + 2, rect);
+	let block = theme.block().title("Recent");
+	f.render_widget(Clear, rect);
+	f.render_widget(List::new(items).block(block), rect);
+}
+
+fn render_global<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	state: &ProxiesState,
+	global_mode: bool,
+	index: usize,
+	accessible: bool,
+	rect: Rect,
+) {
+	let now = state.now(GLOBAL);
+	let items: Vec<_> = state
+		.global_nodes()
+		.into_iter()
+		.enumerate()
+		.map(|(i, node)| {
+			let active = Some(node) == now;
+			let mut style = Style::default();
+			if active {
+				style = style
+					.fg(theme.active)
+					.add_modifier(Modifier::BOLD);
+			}
+			if i == index {
+				style = style.bg(theme.highlight);
+			}
+			let spans = Spans::from(vec![
+				marker(accessible, i == index, active),
+				latency_span(
+					state.latency(node),
+					LatencyDisplay::Number,
+					state.thresholds(),
+					0,
+					accessible,
+				),
+				Span::raw(node.to_owned()),
+			]);
+			ListItem::new(spans).style(style)
+		})
+		.collect();
+
+	let mut title = String::from(GLOBAL);
+	if !global_mode {
+		title.push_str(" (used in global mode)");
+	}
+	let rect = centered_rect(50, items.len() as u16 + 2, rect);
+	let block = theme.block().title(title);
+	// scrolled to the cursor when there are more nodes than rows
+	let mut list_state = ListState::default();
+	list_state.select(Some(index));
+	f.render_widget(Clear, rect);
+	f.render_stateful_widget(
+		List::new(items).block(block),
+		rect,
+		&mut list_state,
+	);
+}
+
+fn render_profiles<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	store: &Store,
+	index: usize,
+	accessible: bool,
+	rect: Rect,
+) {
+	let mut items: Vec<_> = store
+		.profiles
+		.iter()
+		.enumerate()
+		.map(|(i, profile)| {
+			let mut style = Style::default();
+			if i == index {
+				style = style.bg(theme.highlight);
+			}
+			let summary = format!(
+				"  {}, {} groups",
+				profile.mode.as_deref().unwrap_or("-"),
+				profile.selections.len()
+			);
+			let spans = Spans::from(vec![
+				marker(accessible, i == index, false),
+				Span::raw(profile.name.as_str()),
+				Span::styled(
+					summary,
+					Style::default()
+						.add_modifier(Modifier::DIM),
+				),
+			]);
+			ListItem::new(spans).style(style)
+		})
+		.collect();
+	if items.is_empty() {
+		items.push(ListItem::new("No profiles, save one with S"));
+	}
+
+	let rect = centered_rect(50, items.len() as u16 + 2, rect);
+	let block = theme.block().title("Profiles");
+	f.render_widget(Clear, rect);
+	f.render_widget(List::new(items).block(block), rect);
+}
+
+fn render_comparison<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	comparison: &Comparison,
+	rect: Rect,
+) {
+	let [first, second] = &comparison.samples;
+	let cell = |delay: Option<&u16>| match delay {
+		Some(0) => String::from("timeout"),
+		Some(delay) => format!("{}ms", delay),
+		_ => String::new(),
+	};
+	let mut rows: Vec<_> = (0..first.len().max(second.len()))
+		.map(|round| {
+			Row::new(vec![
+				format!("#{}", round + 1),
+				cell(first.get(round)),
+				cell(second.get(round)),
+			])
+		})
+		.collect();
+
+	let stats = [delay_stats(first), delay_stats(second)];
+	let labels = ["min", "avg", "max"];
+	for (i, label) in labels.iter().enumerate() {
+		let value = |stats: Option<(u16, u16, u16)>| {
+			let (min, avg, max) = match stats {
+				Some(stats) => stats,
+				_ => return String::from("-"),
+			};
+			format!("{}ms", [min, avg, max][i])
+		};
+		rows.push(Row::new(vec![
+			label.to_string(),
+			value(stats[0]),
+			value(stats[1]),
+		])
+		.style(Style::default().add_modifier(Modifier::BOLD)));
+	}
+
+	let [a, b] = &comparison.nodes;
+	let header = Row::new(vec!["", a.as_str(), b.as_str()])
+		.style(Style::default().add_modifier(Modifier::BOLD));
+	let height = rows.len() as u16 + 3;
+	let table = Table::new(rows)
+		.header(header)
+		.block(theme
+			.block()
+			.title(format!("Compare ({} rounds)", COMPARE_ROUNDS)))
+		.widths(&[
+			Constraint::Length(6),
+			Constraint::Percentage(45),
+			Constraint::Percentage(45),
+		]);
+
+	let rect = centered_rect(60, height, rect);
+	f.render_widget(Clear, rect);
+	f.render_widget(table, rect);
+}
+
+fn render_confirm<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	action: Action,
+	rect: Rect,
+) {
+	let text = Spans::from(vec![
+		Span::raw(action.question()),
+		Span::styled(
+			"  y/N",
+			Style::default().add_modifier(Modifier::DIM),
+		),
+	]);
+	let width = action.question().chars().count() as u16 + 9;
+	let rect = centered_rect(width, 3, rect);
+	f.render_widget(Clear, rect);
+	f.render_widget(Paragraph::new(text).block(theme.block()), rect);
+}
+
+fn render_dns<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	lookup: &DnsLookup,
+	rect: Rect,
+) {
+	let title = format!("DNS {} {}", lookup.name, lookup.kind);
+	let block = theme.block().title(title);
+	let text = match &lookup.result {
+		Some(Ok(answer)) if answer.answer.is_empty() => {
+			Some(format!("No records ({})", answer.status_name()))
+		}
+		Some(Ok(_)) => None,
+		Some(Err(err)) => Some(err.clone()),
+		None => Some(String::from("Resolving…")),
+	};
+	if let Some(text) = text {
+		let rect = centered_rect(60, 3, rect);
+		f.render_widget(Clear, rect);
+		f.render_widget(Paragraph::new(text).block(block), rect);
+		return;
+	}
+	let answer = match &lookup.result {
+		Some(Ok(answer)) => answer,
+		_ => return,
+	};
+
+	let rows: Vec<_> = answer
+		.answer
+		.iter()
+		.map(|record| {
+			Row::new(vec![
+				record.name.clone(),
+				record.kind_name(),
+				record.ttl.to_string(),
+				record.data.clone(),
+			])
+		})
+		.collect();
+	let header = Row::new(vec!["Name", "Type", "TTL", "Data"])
+		.style(Style::default().add_modifier(Modifier::BOLD));
+	let height = rows.len() as u16 + 3;
+	let table = Table::new(rows).header(header).block(block).widths(&[
+		Constraint::Percentage(35),
+		Constraint::Length(6),
+		Constraint::Length(6),
+		Constraint::Percentage(50),
+	]);
+
+	let rect = centered_rect(80, height, rect);
+	f.render_widget(Clear, rect);
+	f.render_widget(table, rect);
+}
+
+// wide enough for the title and the longest entry
+fn menu_width(
+	routes: &[Route],
+	headers: &[(usize, String)],
+	title: &str,
+) -> u16 {
+	// entries are indented under the section titles, or marked with `> `
+	let entries = routes.iter().map(|r| r.to_string().chars().count() + 2);
+	let titles = headers.iter().map(|(_, t)| t.chars().count());
+	let widest = entries
+		.chain(titles)
+		.chain([title.chars().count()])
+		.max()
+		.unwrap_or(0);
+	widest as u16 + 2
+}
+
+// `title › section › page` for the collapsed menu
+fn breadcrumb<'a>(
+	routes: &[Route],
+	headers: &'a [(usize, String)],
+	page: usize,
+	title: String,
+) -> Spans<'a> {
+	let dim = Style::default().add_modifier(Modifier::DIM);
+	let mut spans = vec![Span::styled(title, dim)];
+	let section = headers.iter().rev().find(|(i, _)| *i <= page);
+	if let Some((_, section)) = section {
+		spans.push(Span::styled(" › ", dim));
+		spans.push(Span::styled(section.as_str(), dim));
+	}
+	if let Some(route) = routes.get(page) {
+		spans.push(Span::styled(" › ", dim));
+		spans.push(Span::styled(
+			route.to_string(),
+			Style::default().add_modifier(Modifier::BOLD),
+		));
+	}
+	Spans::from(spans)
+}
+
+// the route drawn on each row of the menu, None for section titles
+fn menu_rows(len: usize, headers: &[(usize, String)]) -> Vec<Option<usize>> {
+	let mut rows = Vec::new();
+	for index in 0..len {
+		let titles = headers.iter().filter(|(i, _)| *i == index);
+		rows.extend(titles.map(|_| None));
+		rows.push(Some(index));
+	}
+	rows
+}
+
+fn draw_menu<'a>(
+	routes: &[Route],
+	headers: &'a [(usize, String)],
+	page: usize,
+	accessible: bool,
+	theme: &Theme,
+	title: String,
+) -> List<'a> {
+	let mut items = Vec::new();
+	for (index, route) in routes.iter().enumerate() {
+		for (_, title) in headers.iter().filter(|(i, _)| *i == index) {
+			items.push(ListItem::new(Span::styled(
+				title.as_str(),
+				Style::default()
+					.fg(Color::DarkGray)
+					.add_modifier(Modifier::BOLD),
+			)));
+		}
+
+		// indented under its section header, the cursor mark takes
+		// the place of the indent
+		let indent = match (accessible, index == page) {
+			(true, true) => "> ",
+			(true, false) => "  ",
+			_ if headers.is_empty() => "",
+			_ => "  ",
+		};
+		let name = format!("{}{}", indent, route);
+
+		let style = if index == page {
+			Style::default()
+				.bg(theme.highlight)
+				.add_modifier(Modifier::BOLD)
+		} else {
+			Style::default()
+		};
+
+		let spans = Spans::from(Span::styled(
+			name,
+			Style::default().add_modifier(Modifier::ITALIC),
+		));
+
+		items.push(ListItem::new(spans).style(style));
+	}
+
+	let menu = List::new(items).block(theme.block().title(title));
+
+	menu
+}
+
+fn default_menu() -> Vec<MenuSection> {
+	let section = |title: &str, routes: &[Route]| MenuSection {
+		title: title.to_owned(),
+		routes: routes.to_vec(),
+	};
+	vec![
+		section("Status", &[Route::General]),
+		section(
+			"Proxies",
+			&[Route::Proxies, Route::Providers, Route::Rules],
+		),
+		section("Debug", &[Route::Connections, Route::Logs]),
+	]
+}
+
+fn render_main<B: Backend>(f: &mut Frame<B>, app: &mut App, rect: Rect) {
+	let route = app.routes.get(app.page).unwrap_or(&Route::General);
+	let focus = &app.focus;
+	let accessible = app.accessible;
+	match route {
+		Route::General => render_general(
+			f,
+			&app.theme,
+			&mut app.general_state,
+			&app.proxies_state,
+			focus,
+			accessible,
+			rect,
+		),
+		Route::Proxies => render_proxies(
+			f,
+			&app.theme,
+			&mut app.proxies_state,
+			focus,
+			accessible,
+			rect,
+		),
+		Route::Providers => render_providers(
+			f,
+			&app.theme,
+			&app.providers_state,
+			focus,
+			accessible,
+			rect,
+		),
+		Route::Rules => render_rules(
+			f,
+			&app.theme,
+			&mut app.rules_state,
+			&app.proxies_state,
+			focus,
+			rect,
+		),
+		Route::Connections => render_connections(
+			f,
+			&app.theme,
+			&mut app.connections_state,
+			focus,
+			accessible,
+			app.full_chains,
+			rect,
+		),
+		Route::Logs => render_logs(
+			f,
+			&app.theme,
+			&mut app.logs_state,
+			focus,
+			accessible,
+			rect,
+		),
+	}
+}
+
+// one line for the mode and one per group, for the shell after quitting
+fn summary(general: &GeneralState, proxies: &ProxiesState) -> String {
+	let mode = match &general.config {
+		Some(config) => config.mode.as_str(),
+		_ => "unknown",
+	};
+	let mut text = format!("mode: {}\n", mode);
+	if proxies.proxies.is_some() {
+		for group in proxies.providers() {
+			let now = group.now.as_deref().unwrap_or("-");
+			text.push_str(&format!(
+				"{} → {}\n",
+				group.name,
+				proxies.resolve(now)
+			));
+		}
+	}
+	text
+}
+
+fn render_general<'a, B: Backend>(
+	f: &'a mut Frame<B>,
+	theme: &Theme,
+	state: &mut GeneralState,
+	proxies_state: &ProxiesState,
+	focus: &'a Pane,
+	accessible: bool,
+	rect: Rect,
+) {
+	let items: Vec<_> = Mode::ALL
+		.iter()
+		.enumerate()
+		.map(|(i, mode)| {
+			let mut style = Style::default();
+			let active = state.is_active(mode);
+			if active {
+				style = style
+					.fg(theme.active)
+					.add_modifier(Modifier::BOLD);
+			}
+			let cursor =
+				i == state.index && focus == &Pane::General;
+			if cursor {
+				style = style.bg(theme.highlight);
+			}
+
+			let mut spans = vec![
+				marker(accessible, cursor, active),
+				Span::raw(mode.as_str()),
+			];
+			if let Some(reason) = state.unavailable(mode) {
+				style = style.add_modifier(Modifier::DIM);
+				spans.push(Span::raw(format!(" ({})", reason)));
+			}
+			let spans = Spans::from(spans);
+
+			ListItem::new(spans).style(style)
+		})
+		.collect();
+
+	let chunks = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints(
+			[
+				Constraint::Length(Mode::ALL.len() as u16 + 2),
+				Constraint::Length(4),
+				Constraint::Min(0),
+			]
+			.as_ref(),
+		)
+		.split(rect);
+
+	let mut title = String::from("General");
+	match &state.config {
+		None => title.push_str(" (mode not loaded)"),
+		// not one of the listed modes, set by the core or another client
+		Some(Config {
+			mode: Mode::Other(name),
+			..
+		}) => title.push_str(&format!(" (in {} mode)", name)),
+		_ => {}
+	}
+	let block = theme.block().title(title);
+	let list = List::new(items).block(block);
+	state.list_state.select(Some(state.index));
+	f.render_stateful_widget(list, chunks[0], &mut state.list_state);
+
+	let config = state.config.as_ref();
+	let allow_lan = match config.and_then(|c| c.allow_lan) {
+		Some(true) => "on",
+		Some(false) => "off",
+		_ => "-",
+	};
+	let log_level = config
+		.and_then(|c| c.log_level)
+		.map_or(String::from("-"), |level| level.to_string());
+	let dim = Style::default().add_modifier(Modifier::DIM);
+	let settings = vec![
+		ListItem::new(Spans::from(vec![
+			Span::styled("allow-lan  ", dim),
+			Span::raw(allow_lan),
+		])),
+		ListItem::new(Spans::from(vec![
+			Span::styled("log-level  ", dim),
+			Span::raw(log_level),
+		])),
+	];
+	let block = theme.block().title("Settings");
+	f.render_widget(List::new(settings).block(block), chunks[1]);
+
+	// loaded separately, either may be missing
+	let items: Vec<_> = match &proxies_state.proxies {
+		Some(_) => proxies_state
+			.providers()
+			.into_iter()
+			.map(|group| {
+				let now = group.now.as_deref().unwrap_or("-");
+				ListItem::new(Spans::from(vec![
+					Span::raw(group.name.as_str()),
+					Span::raw(" → "),
+					Span::raw(proxies_state.resolve(now)),
+				]))
+			})
+			.collect(),
+		_ => vec![ListItem::new("Selections not loaded")],
+	};
+	let block = theme.block().title("Selections");
+	f.render_widget(List::new(items).block(block), chunks[2]);
+}
+
+fn render_proxies<'a, B: Backend>(
+	f: &'a mut Frame<B>,
+	theme: &Theme,
+	state: &mut ProxiesState,
+	focus: &'a Pane,
+	accessible: bool,
+	rect: Rect,
+) {
+	let chunks = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints(
+			[Constraint::Length(3), Constraint::Min(0)].as_ref(),
+		)
+		.split(rect);
+
+	let block = Block::default().style(Style::default());
+	f.render_widget(block, rect);
+
+	if state.providers_len == 0 {
+		state.list_area = Rect::default();
+		return;
+	}
+
+	let inner = chunks[1].inner(&Margin {
+		vertical: 1,
+		horizontal: 1,
+	});
+	state.list_area = inner;
+	// only the group view, the flat one lists each group too
+	let columns = if state.columns && state.view == ProxiesView::Grouped {
+		(inner.width / GRID_COLUMN_WIDTH).max(1)
+	} else {
+		1
+	};
+	state.grid_rows = if columns > 1 {
+		(inner.height as usize).max(1)
+	} else {
+		state.list_offset = list_offset(
+			state.list_offset,
+			state.proxy_index,
+			state.proxies_len,
+			inner.height as usize,
+		);
+		0
+	};
+
+	if state.view == ProxiesView::Flat {
+		render_flat_proxies(
+			f, theme, state, focus, accessible, &chunks,
+		);
+		return;
+	}
+
+	let providers = state.providers();
+
+	let names: Vec<_> = providers
+		.iter()
+		.enumerate()
+		.map(|(i, p)| {
+			let selected =
+				i == state.provider && focus == &Pane::Proxies;
+			let mut name = if accessible && selected {
+				format!("> {}", p.name)
+			} else {
+				p.name.clone()
+			};
+			if !p.is_selector() {
+				name.push_str(&format!(" ({})", p.kind));
+			}
+			// the node in use may be scrolled out of the list below
+			if let (true, Some(now)) = (i == state.provider, &p.now)
+			{
+				name.push_str(&format!(" → {}", now));
+			}
+			name
+		})
+		.collect();
+	let widths: Vec<_> = names.iter().map(|n| n.chars().count()).collect();
+	let available = chunks[0].width.saturating_sub(2) as usize;
+	let (start, end) = tab_window(&widths, state.provider, available);
+
+	let mut titles = Vec::new();
+	if start > 0 {
+		titles.push(Spans::from("‹"));
+	}
+	titles.extend(names[start..end].iter().map(|n| Spans::from(n.clone())));
+	if end < names.len() {
+		titles.push(Spans::from("›"));
+	}
+	let selected = state.provider - start + usize::from(start > 0);
+
+	let mut tabs = Tabs::new(titles)
+		.block(theme.block().title(proxies_title(state)))
+		.style(Style::default())
+		.highlight_style(Style::default().add_modifier(Modifier::BOLD));
+
+	tabs = tabs.select(selected);
+	if focus == &Pane::Proxies {
+		tabs = tabs.highlight_style(
+			Style::default()
+				.fg(theme.selection)
+				.add_modifier(Modifier::BOLD),
+		);
+	}
+
+	f.render_widget(tabs, chunks[0]);
+
+	let provider = providers[state.provider];
+	let titles = state.nodes(provider);
+	let len = titles.len();
+	let item = |t: &str, cursor: bool, width: u16| {
+		let mut style = Style::default();
+		let active = Some(t) == provider.now.as_deref();
+		if active {
+			style = style
+				.fg(theme.active)
+				.add_modifier(Modifier::BOLD);
+		}
+		let cursor = cursor && focus == &Pane::Proxies;
+		if cursor {
+			style = style.bg(theme.highlight);
+		}
+		ListItem::new(Spans::from(vec![
+			marker(accessible, cursor, active),
+			latency_span(
+				state.latency(t),
+				state.latency_display,
+				state.thresholds(),
+				width,
+				accessible,
+			),
+			Span::raw(t.to_owned()),
+			Span::raw(if state.marked.as_deref() == Some(t) {
+				" (marked)"
+			} else {
+				""
+			}),
+		]))
+		.style(style)
+	};
+
+	let mut title = format!("sort: {}", state.sort_order(&provider.name));
+	let tested_with = state
+		.highlighted()
+		.and_then(|(_, node)| state.tested_with.get(node));
+	if let Some(url) = tested_with {
+		title.push_str(&format!(" · tested with {}", url));
+	}
+	let block = theme.block().style(Style::default()).title(title);
+
+	if columns == 1 {
+		let items: Vec<_> = titles
+			.iter()
+			.enumerate()
+			.map(|(i, t)| {
+				item(t, i == state.proxy_index, chunks[1].width)
+			})
+			.collect();
+		state.list_state.select(Some(state.proxy_index));
+		f.render_stateful_widget(
+			List::new(items).block(block),
+			chunks[1],
+			&mut state.list_state,
+		);
+		render_position(f, chunks[1], state.proxy_index, len);
+		return;
+	}
+
+	// flowed top to bottom, then left to right, a page at a time
+	f.render_widget(block, chunks[1]);
+	render_position(f, chunks[1], state.proxy_index, len);
+	let rows = state.grid_rows;
+	let page = rows * columns as usize;
+	let first = state.proxy_index / page * page;
+	let rects = Layout::default()
+		.direction(Direction::Horizontal)
+		.constraints(vec![
+			Constraint::Ratio(1, columns as u32);
+			columns as usize
+		])
+		.split(inner);
+	for (column, rect) in rects.into_iter().enumerate() {
+		let start = first + column * rows;
+		let items: Vec<_> = titles
+			.iter()
+			.enumerate()
+			.skip(start)
+			.take(rows)
+			.map(|(i, t)| {
+				item(t, i == state.proxy_index, rect.width)
+			})
+			.collect();
+		f.render_widget(List::new(items), rect);
+	}
+}
+
+// The first item shown by a List of one line items. Like the List itself it
+// scrolls only as far as it takes to keep `selected` in view.
+fn list_offset(
+	offset: usize,
+	selected: usize,
+	len: usize,
+	height: usize,
+) -> usize {
+	let offset = offset.min(len.saturating_sub(1));
+	if height == 0 {
+		offset
+	} else if selected >= offset + height {
+		selected + 1 - height
+	} else {
+		offset.min(selected)
+	}
+}
+
+fn inside(rect: Rect, x: u16, y: u16) -> bool {
+	x >= rect.x
+		&& x < rect.x + rect.width
+		&& y >= rect.y && y < rect.y + rect.height
+}
+
+// narrowest column the node list is split into, see ProxiesState::columns
+const GRID_COLUMN_WIDTH: u16 = 40;
+
+// The range of tabs that fits in `available` columns and includes
+// `selected`, leaving room for the arrows marking hidden tabs. Each tab takes
+// its width plus a space either side and a divider.
+fn tab_window(
+	widths: &[usize],
+	selected: usize,
+	available: usize,
+) -> (usize, usize) {
+	let cost = |range: &[usize]| -> usize {
+		range.iter().map(|w| w + 3).sum()
+	};
+	if cost(widths) <= available {
+		return (0, widths.len());
+	}
+	// room for both arrows, each a one column tab
+	let available = available.saturating_sub(8);
+
+	let mut start = 0;
+	while start < selected && cost(&widths[start..=selected]) > available {
+		start += 1;
+	}
+	let mut end = selected + 1;
+	while end < widths.len() && cost(&widths[start..=end]) <= available {
+		end += 1;
+	}
+	(start, end)
+}
+
+fn proxies_title(state: &ProxiesState) -> String {
+	let mut title = match state.hidden_len() {
+		0 => String::from("Proxies"),
+		len if state.show_hidden => {
+			format!("Proxies ({} hidden shown)", len)
+		}
+		len => format!("Proxies ({} hidden)", len),
+	};
+	match state.tab_order {
+		TabOrder::Configured => {}
+		TabOrder::Alphabetical => title.push_str(" · tabs by name"),
+		TabOrder::Latency => title.push_str(" · tabs by latency"),
+	}
+	if !state.filter.is_empty() {
+		title.push_str(&format!(" \"{}\"", state.filter));
+	}
+	title
+}
+
+fn render_flat_proxies<'a, B: Backend>(
+	f: &'a mut Frame<B>,
+	theme: &Theme,
+	state: &mut ProxiesState,
+	focus: &'a Pane,
+	accessible: bool,
+	chunks: &[Rect],
+) {
+	let mut highlight = Style::default().add_modifier(Modifier::BOLD);
+	if focus == &Pane::Proxies {
+		highlight = highlight.fg(theme.selection);
+	}
+	let tabs = Tabs::new(vec![Spans::from("All groups")])
+		.block(theme.block().title(proxies_title(state)))
+		.highlight_style(highlight)
+		.select(0);
+	f.render_widget(tabs, chunks[0]);
+
+	let rows = state.flat_rows();
+	let len = rows.len();
+	let items: Vec<_> = rows
+		.into_iter()
+		.enumerate()
+		.map(|(i, (provider, node))| {
+			let mut style = Style::default();
+			let active = Some(node) == provider.now.as_deref();
+			if active {
+				style = style
+					.fg(theme.active)
+					.add_modifier(Modifier::BOLD);
+			}
+			let cursor = i == state.proxy_index
+				&& focus == &Pane::Proxies;
+			if cursor {
+				style = style.bg(theme.highlight);
+			}
+			ListItem::new(Spans::from(vec![
+				marker(accessible, cursor, active),
+				latency_span(
+					state.latency(node),
+					state.latency_display,
+					state.thresholds(),
+					chunks[1].width,
+					accessible,
+				),
+				Span::raw(node.to_owned()),
+				Span::styled(
+					format!("  {}", provider.name),
+					Style::default()
+						.add_modifier(Modifier::DIM),
+				),
+			]))
+			.style(style)
+		})
+		.collect();
+
+	let block = theme.block();
+	state.list_state.select(Some(state.proxy_index));
+	f.render_stateful_widget(
+		List::new(items).block(block),
+		chunks[1],
+		&mut state.list_state,
+	);
+	render_position(f, chunks[1], state.proxy_index, len);
+}
+
+fn render_providers<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	state: &ProvidersState,
+	focus: &Pane,
+	accessible: bool,
+	rect: Rect,
+) {
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs());
+	let providers = match &state.providers {
+		Some(providers) => providers.as_slice(),
+		_ => &[],
+	};
+	let rows: Vec<_> = providers
+		.iter()
+		.map(|provider| {
+			let updated = provider
+				.updated_at
+				.as_deref()
+				.map_or(String::from("-"), format_timestamp);
+			let (usage, expires, warning) = match &provider
+				.subscription_info
+			{
+				Some(info) => (
+					match info.total {
+						0 => format_bytes(info.used()),
+						total => {
+							format!(
+								"{} / {}",
+								format_bytes(info.used()),
+								format_bytes(total)
+							)
+						}
+					},
+					match info.expire {
+						0 => String::from("never"),
+						expire => format_date(expire),
+					},
+					info.warning(now),
+				),
+				_ => (
+					String::from("-"),
+					String::from("-"),
+					None,
+				),
+			};
+			let alive = match provider.alive() {
+				Some((alive, len)) => {
+					format!("{}/{}", alive, len)
+				}
+				_ => String::from("-"),
+			};
+			let mut row = Row::new(vec![
+				provider.name.clone(),
+				provider.vehicle_type.clone(),
+				alive,
+				updated,
+				usage,
+				expires,
+				match state.busy.get(&provider.name) {
+					Some(busy) => busy,
+					_ => warning
+						.map_or("", |(text, _)| text),
+				}
+				.to_owned(),
+			]);
+			if let Some((_, color)) = warning {
+				row = row.style(Style::default().fg(color));
+			}
+			row
+		})
+		.collect();
+
+	let title = match &state.providers {
+		Some(providers) => format!("Providers ({})", providers.len()),
+		_ => String::from("Providers (not loaded)"),
+	};
+	let header = Row::new(vec![
+		"Name", "Type", "Alive", "Updated", "Used", "Expires", "",
+	])
+	.style(Style::default().add_modifier(Modifier::BOLD));
+	let mut table = Table::new(rows)
+		.header(header)
+		.block(theme.block().title(title))
+		.widths(&[
+			Constraint::Percentage(20),
+			Constraint::Length(4),
+			Constraint::Length(5),
+			Constraint::Length(16),
+			Constraint::Length(19),
+			Constraint::Length(10),
+			Constraint::Length(12),
+		]);
+	if focus == &Pane::Providers {
+		table = table
+			.highlight_style(Style::default().bg(theme.highlight));
+		if accessible {
+			table = table.highlight_symbol("> ");
+		}
+	}
+
+	let mut table_state = TableState::default();
+	if !providers.is_empty() {
+		table_state.select(Some(state.index));
+	}
+	f.render_stateful_widget(table, rect, &mut table_state);
+}
+
+// `2024-01-31T08:00:00.123+08:00` to `2024-01-31 08:00`
+fn format_timestamp(text: &str) -> String {
+	text.chars()
+		.take(16)
+		.map(|c| if c == 'T' { ' ' } else { c })
+		.collect()
+}
+
+// `2024-05-01T08:30:00.123+08:00` to unix seconds, the fraction dropped
+fn parse_timestamp(text: &str) -> Option<u64> {
+	let (date, time) = text.split_once('T')?;
+	let mut date = date.splitn(3, '-').map(|n| n.parse::<i64>());
+	let (year, month, day) =
+		(date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+	let clock = time.get(..8)?;
+	let mut clock = clock.splitn(3, ':').map(|n| n.parse::<i64>());
+	let (hour, minute, second) = (
+		clock.next()?.ok()?,
+		clock.next()?.ok()?,
+		clock.next()?.ok()?,
+	);
+
+	let zone = time[8..]
+		.trim_start_matches(|c: char| c == '.' || c.is_ascii_digit());
+	let offset = match zone {
+		"Z" | "z" => 0,
+		_ => {
+			let sign = match zone.get(..1)? {
+				"+" => 1,
+				"-" => -1,
+				_ => return None,
+			};
+			let (hours, minutes) = zone[1..].split_once(':')?;
+			sign * (hours.parse::<i64>().ok()? * 3600
+				+ minutes.parse::<i64>().ok()? * 60)
+		}
+	};
+
+	// the reverse of format_date, after Howard Hinnant's
+	// `days_from_civil`
+	let year = year - i64::from(month <= 2);
+	let era = year.div_euclid(400);
+	let year_of_era = year - era * 400;
+	let month = if month > 2 { month - 3 } else { month + 9 };
+	let day_of_year = (153 * month + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4
+		- year_of_era / 100
+		+ day_of_year;
+	let days = era * 146097 + day_of_era - 719468;
+	let secs = days * 86400 + hour * 3600 + minute * 60 + second - offset;
+	u64::try_from(secs).ok()
+}
+
+// the two largest units, `45s`, `12m 05s`, `3h 20m` or `2d 04h`, only the
+// days from ten days on
+fn format_duration(secs: u64) -> String {
+	match secs {
+		0..=59 => format!("{}s", secs),
+		60..=3599 => format!("{}m {:02}s", secs / 60, secs % 60),
+		3600..=86399 => {
+			format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+		}
+		86400..=863999 => {
+			format!("{}d {:02}h", secs / 86400, secs % 86400 / 3600)
+		}
+		_ => format!("{}d", secs / 86400),
+	}
+}
+
+// unix seconds to a UTC `YYYY-MM-DD`
+fn format_date(secs: u64) -> String {
+	// days to a civil date, after Howard Hinnant's `civil_from_days`
+	let days = (secs / 86400) as i64 + 719468;
+	let era = days / 146097;
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524
+		- day_of_era / 146096)
+		/ 365;
+	let day_of_year = day_of_era
+		- (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let mp = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	let year = year_of_era + era * 400 + i64::from(month <= 2);
+	format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn render_rules<'a, B: Backend>(
+	f: &'a mut Frame<B>,
+	theme: &Theme,
+	state: &mut RulesState,
+	proxies_state: &ProxiesState,
+	focus: &'a Pane,
+	rect: Rect,
+) {
+	let chunks = Layout::default()
+		.direction(Direction::Vertical)
+		.constraints(
+			[Constraint::Length(7), Constraint::Min(0)].as_ref(),
+		)
+		.split(rect);
+	state.rows = chunks[1].height.saturating_sub(2) as usize;
+
+	let label = Style::default().add_modifier(Modifier::BOLD);
+	let mut lines = Vec::new();
+
+	if !state.host.is_empty() {
+		lines.push(Spans::from(vec![
+			Span::styled("Host: ", label),
+			Span::raw(state.host.as_str()),
+		]));
+		match state.matched() {
+			Some(rule) => {
+				lines.push(Spans::from(vec![
+					Span::styled("Rule: ", label),
+					Span::raw(format!(
+						"{},{}",
+						rule.kind, rule.payload
+					)),
+					source_suffix(rule),
+				]));
+				let node = proxies_state.resolve(&rule.proxy);
+				let proxy = if node == rule.proxy {
+					rule.proxy.clone()
+				} else {
+					format!("{} → {}", rule.proxy, node)
+				};
+				lines.push(Spans::from(vec![
+					Span::styled("Proxy: ", label),
+					Span::styled(
+						proxy,
+						Style::default()
+							.fg(theme.active),
+					),
+				]));
+			}
+			_ => lines.push(Spans::from("No rule matched")),
+		}
+	} else if focus == &Pane::Rules {
+		lines.push(Spans::from(
+			"Press m to preview which rule matches a host",
+		));
+		lines.push(Spans::from(
+			"Press e to export the rules to a file",
+		));
+	}
+
+	if let Some(rules) = &state.rules {
+		lines.push(Spans::from(""));
+		lines.push(Spans::from(Span::styled(
+			format!(
+				"{} rules loaded. The preview only evaluates DOMAIN, \
+				 DOMAIN-SUFFIX, DOMAIN-KEYWORD and IP-CIDR rules and \
+				 is an approximation of Clash's own matcher.",
+				rules.len()
+			),
+			Style::default().add_modifier(Modifier::DIM),
+		)));
+	}
+
+	let block = theme.block().title("Rules");
+	let paragraph =
+		Paragraph::new(lines).block(block).wrap(Wrap { trim: true });
+	f.render_widget(paragraph, chunks[0]);
+
+	let rules = state.filtered();
+	let title = format!("{} rules ({})", rules.len(), state.filter);
+	let placeholder = match &state.rules {
+		None => Some("Rules not loaded"),
+		Some(all) if all.is_empty() => Some("No rules"),
+		_ if rules.is_empty() => Some("No rules match the filter"),
+		_ => None,
+	};
+	if let Some(text) = placeholder {
+		let block = theme.block().title(title);
+		let text = Span::styled(
+			text,
+			Style::default().add_modifier(Modifier::DIM),
+		);
+		f.render_widget(Paragraph::new(text).block(block), chunks[1]);
+		return;
+	}
+	let items: Vec<_> = rules
+		.iter()
+		.skip(state.offset)
+		.map(|rule| {
+			ListItem::new(Spans::from(vec![
+				Span::raw(format!(
+					"{},{}",
+					rule.kind, rule.payload
+				)),
+				Span::styled(
+					format!(" → {}", rule.proxy),
+					Style::default().fg(theme.active),
+				),
+				source_suffix(rule),
+			]))
+		})
+		.collect();
+
+	let block = theme.block().title(title);
+	f.render_widget(List::new(items).block(block), chunks[1]);
+	// the rules have no cursor, this is the topmost one shown
+	render_position(f, chunks[1], state.offset, rules.len());
+}
+
+fn source_suffix(rule: &Rule) -> Span<'_> {
+	let source = match rule.source() {
+		Some(source) => format!(" [{}]", source),
+		_ => String::new(),
+	};
+	Span::styled(source, Style::default().add_modifier(Modifier::DIM))
+}
+
+fn render_connections<'a, B: Backend>(
+	f: &'a mut Frame<B>,
+	theme: &Theme,
+	state: &mut ConnectionsState,
+	focus: &'a Pane,
+	accessible: bool,
+	full_chains: bool,
+	rect: Rect,
+) {
+	if state.grouping != Grouping::None {
+		// less the borders and the header
+		state.rows = rect.height.saturating_sub(3) as usize;
+		render_connection_groups(
+			f, theme, state, focus, accessible, rect,
+		);
+		return;
+	}
+
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |d| d.as_secs());
+	let visible = state.visible();
+	let rows: Vec<_> = visible
+		.iter()
+		.map(|connection| {
+			// Clash lists the node first and the rule's group last
+			let chains: Vec<_> = if full_chains {
+				connection
+					.chains
+					.iter()
+					.rev()
+					.map(|s| &**s)
+					.collect()
+			} else {
+				connection
+					.chains
+					.iter()
+					.take(1)
+					.map(|s| &**s)
+					.collect()
+			};
+			Row::new(vec![
+				connection.metadata.network.to_uppercase(),
+				connection.host().to_owned(),
+				chains.join(" → "),
+				connection.started().map_or(
+					String::from("-"),
+					|at| {
+						format_duration(
+							now.saturating_sub(at),
+						)
+					},
+				),
+				format_bytes(connection.upload),
+				format_bytes(connection.download),
+			])
+		})
+		.collect();
+
+	let arrow = if state.ascending { "▲" } else { "▼" };
+	let column = |sort: ConnectionSort, name: &str| {
+		if state.sort == sort {
+			format!("{} {}", name, arrow)
+		} else {
+			name.to_owned()
+		}
+	};
+	let header = Row::new(vec![
+		String::from("Net"),
+		String::from("Host"),
+		String::from("Chains"),
+		column(ConnectionSort::Duration, "Time"),
+		column(ConnectionSort::Upload, "Upload"),
+		column(ConnectionSort::Download, "Download"),
+	])
+	.style(Style::default().add_modifier(Modifier::BOLD));
+	let mut table = Table::new(rows)
+		.header(header)
+		.block(theme
+			.block()
+			.title(connections_title(state, visible.len())))
+		.widths(&[
+			Constraint::Length(4),
+			Constraint::Percentage(24),
+			Constraint::Percentage(24),
+			Constraint::Percentage(9),
+			Constraint::Percentage(13),
+			Constraint::Percentage(13),
+		]);
+	if focus == &Pane::Connections {
+		table = table
+			.highlight_style(Style::default().bg(theme.highlight));
+		if accessible {
+			table = table.highlight_symbol("> ");
+		}
+	}
+
+	let mut table_rect = rect;
+	let selected = state.selected().filter(|_| focus == &Pane::Connections);
+	if let Some(connection) = selected {
+		let chunks = Layout::default()
+			.direction(Direction::Vertical)
+			.constraints(
+				[Constraint::Min(0), Constraint::Length(8)]
+					.as_ref(),
+			)
+			.split(rect);
+		table_rect = chunks[0];
+		render_connection_rates(f, theme, state, connection, chunks[1]);
+	}
+
+	let mut table_state = TableState::default();
+	if !visible.is_empty() {
+		table_state.select(Some(state.index));
+	}
+	f.render_stateful_widget(table, table_rect, &mut table_state);
+	render_position(f, table_rect, state.index, visible.len());
+
+	// what the rows shown add up to, left on the bottom border
+	let upload: u64 = visible.iter().map(|c| c.upload).sum();
+	let download: u64 = visible.iter().map(|c| c.download).sum();
+	let text = format!(
+		" ↑ {}  ↓ {} ",
+		format_bytes(upload),
+		format_bytes(download)
+	);
+	let width = text.chars().count() as u16;
+	if table_rect.width >= width + 16 && table_rect.height >= 2 {
+		let rect = Rect::new(
+			table_rect.x + 1,
+			table_rect.y + table_rect.height - 1,
+			width,
+			1,
+		);
+		f.render_widget(Paragraph::new(text), rect);
+	}
+	state.rows = table_rect.height.saturating_sub(3) as usize;
+}
+
+fn connections_title(state: &ConnectionsState, visible: usize) -> String {
+	let mut title = match state.network {
+		NetworkFilter::All if state.filter.is_empty() => {
+			format!("Connections ({})", visible)
+		}
+		NetworkFilter::All => format!(
+			"Connections ({} of {})",
+			visible,
+			state.connections.len()
+		),
+		NetworkFilter::Tcp => {
+			format!(
+				"Connections ({} of {}, TCP only)",
+				visible,
+				state.connections.len()
+			)
+		}
+		NetworkFilter::Udp => {
+			format!(
+				"Connections ({} of {}, UDP only)",
+				visible,
+				state.connections.len()
+			)
+		}
+	};
+	if !state.filter.is_empty() {
+		title.push_str(&format!(" \"{}\"", state.filter));
+	}
+	title
+}
+
+fn render_connection_groups<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	state: &ConnectionsState,
+	focus: &Pane,
+	accessible: bool,
+	rect: Rect,
+) {
+	let groups = state.groups();
+	let rows: Vec<_> = groups
+		.iter()
+		.map(|group| {
+			Row::new(vec![
+				group.key.to_owned(),
+				group.count.to_string(),
+				format_bytes(group.upload),
+				format_bytes(group.download),
+			])
+		})
+		.collect();
+
+	let (column, by) = match state.grouping {
+		Grouping::Host => ("Host", "host"),
+		_ => ("Process", "process"),
+	};
+	let header =
+		Row::new(vec![column, "Connections", "Upload", "Download"])
+			.style(Style::default().add_modifier(Modifier::BOLD));
+	let title = format!(
+		"{} by {}",
+		connections_title(state, state.visible().len()),
+		by
+	);
+	let mut table = Table::new(rows)
+		.header(header)
+		.block(theme.block().title(title))
+		.widths(&[
+			Constraint::Percentage(55),
+			Constraint::Percentage(15),
+			Constraint::Percentage(15),
+			Constraint::Percentage(15),
+		]);
+	if focus == &Pane::Connections {
+		table = table
+			.highlight_style(Style::default().bg(theme.highlight));
+		if accessible {
+			table = table.highlight_symbol("> ");
+		}
+	}
+
+	let mut table_state = TableState::default();
+	if !groups.is_empty() {
+		table_state
+			.select(Some(state.group_index.min(groups.len() - 1)));
+	}
+	f.render_stateful_widget(table, rect, &mut table_state);
+}
+
+fn render_connection_rates<B: Backend>(
+	f: &mut Frame<B>,
+	theme: &Theme,
+	state: &ConnectionsState,
+	connection: &Connection,
+	rect: Rect,
+) {
+	let history = state.rates.get(&connection.id);
+	let (up, down): (Vec<_>, Vec<_>) =
+		history.into_iter().flatten().copied().unzip();
+	let (last_up, last_down) =
+		history.and_then(|h| h.back()).copied().unwrap_or_default();
+
+	let block = theme.block().title(connection.host().to_owned());
+	let inner = block.inner(rect);
+	f.render_widget(block, rect);
+
+	let chunks = Layout::default()
+		.direction(Direction::Horizontal)
+		.constraints(
+			[
+				Constraint::Percentage(50),
+				Constraint::Percentage(50),
+			]
+			.as_ref(),
+		)
+		.split(inner);
+	let graphs = [
+		(format!("↑ {}", format_speed(last_up)), &up, Color::Yellow),
+		(format!("↓ {}", format_speed(last_down)), &down, Color::Cyan),
+	];
+	for ((title, data, color), rect) in graphs.iter().zip(chunks) {
+		// newest samples on the right edge
+		let skip = data.len().saturating_sub(rect.width as usize);
+		let sparkline = Sparkline::default()
+			.block(Block::default().title(title.as_str()))
+			.style(Style::default().fg(*color))
+			.data(&data[skip..]);
+		f.render_widget(sparkline, rect);
+	}
+}
+
+fn render_logs<'a, B: Backend>(
+	f: &'a mut Frame<B>,
+	theme: &Theme,
+	state: &mut LogsState,
+	focus: &'a Pane,
+	accessible: bool,
+	rect: Rect,
+) {
+	let mut title = state.title();
+	if accessible && focus == &Pane::Logs {
+		title = format!("> {}", title);
+	}
+
+	// newest at the bottom, older entries scroll off the top
+	let height = rect.height.saturating_sub(2) as usize;
+	state.rows = height;
+	let mut items: Vec<_> = state
+		.filtered()
+		.rev()
+		.skip(state.scroll)
+		.take(height)
+		.map(|entry| {
+			ListItem::new(Spans::from(vec![
+				Span::raw(format!("{:<8}", entry.level)),
+				Span::raw(entry.payload.as_str()),
+			]))
+			.style(log_style(&entry.level))
+		})
+		.collect();
+	items.reverse();
+
+	let mut block = theme.block().title(title);
+	if focus == &Pane::Logs {
+		block = block
+			.border_style(Style::default().fg(theme.selection));
+	}
+	f.render_widget(List::new(items).block(block), rect);
+}
+
+fn log_style(level: &str) -> Style {
+	match level {
+		"error" => Style::default().fg(Color::Red),
+		"warning" => Style::default().fg(Color::Yellow),
+		"debug" => Style::default().add_modifier(Modifier::DIM),
+		_ => Style::default(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::{
+		io::BufRead,
+		sync::{Mutex, OnceLock},
+	};
+	use tokio::runtime::Runtime;
+
+	// App spawns its requests on the runtime entered on the calling
+	// thread, as main does
+	fn runtime() -> &'static Runtime {
+		static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+		RUNTIME.get_or_init(|| Runtime::new().unwrap())
+	}
+
+	// applies the next result of a request the app sent
+	fn receive(app: &mut App) {
+		let message =
+			app.rx.recv_timeout(Duration::from_secs(5)).unwrap();
+		app.handle(message);
+	}
+
+	fn receive_until(app: &mut App, done: impl Fn(&App) -> bool) {
+		while !done(app) {
+			receive(app);
+		}
+	}
+
+	const PROXIES: &str = r#"{
+		"GLOBAL": {"name": "GLOBAL", "all": ["A", "B"], "now": "A"},
+		"A": {"name": "A"},
+		"B": {"name": "B"}
+	}"#;
+
+	fn parse_proxies(body: &str) -> HashMap<String, Proxy> {
+		serde_json::from_str::<ProxyList>(body)
+			.unwrap()
+			.into_proxies()
+	}
+
+	#[test]
+	fn fetches_sent_before_a_selection_do_not_revert_it() {
+		let mut state = ProxiesState::default();
+		let before = state.sequence.fetch();
+		state.sequence.mutate();
+		let after = state.sequence.fetch();
+
+		// the fetch sent after the selection answers first
+		let selected =
+			PROXIES.replace(r#""now": "A""#, r#""now": "B""#);
+		state.receive(after, Some(parse_proxies(&selected)));
+		state.receive(before, Some(parse_proxies(PROXIES)));
+		assert_eq!(state.now("GLOBAL"), Some("B"));
+
+		let mut state = GeneralState::new();
+		let before = state.sequence.fetch();
+		state.sequence.mutate();
+		let after = state.sequence.fetch();
+		let config = |mode: &str| {
+			Some(Config {
+				mode: Mode::from(mode.to_owned()),
+				allow_lan: None,
+				log_level: None,
+			})
+		};
+		state.receive(after, config("direct"));
+		state.receive(before, config("rule"));
+		assert!(state.is_active(&Mode::Direct));
+	}
+
+	#[test]
+	fn accessible_dots_differ_by_shape_not_only_color() {
+		let dot = |latency, accessible| {
+			latency_span(
+				latency,
+				LatencyDisplay::Dot,
+				LATENCY_THRESHOLDS,
+				0,
+				accessible,
+			)
+			.content
+			.into_owned()
+		};
+		let latencies = [
+			Latency::Untested,
+			Latency::Timeout,
+			Latency::Delay(100),
+			Latency::Delay(300),
+			Latency::Delay(900),
+		];
+		let shapes: Vec<_> =
+			latencies.iter().map(|l| dot(*l, true)).collect();
+		assert_eq!(shapes, ["· ", "× ", "● ", "◐ ", "○ "]);
+		assert!(latencies.iter().all(|l| dot(*l, false) == "● "));
+	}
+
+	#[test]
+	fn latency_tab_order_keeps_the_selected_tab() {
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(
+				r#"{
+				"Asia": {"name": "Asia", "all": ["A"], "now": "A"},
+				"Europe": {"name": "Europe", "all": ["B"], "now": "B"},
+				"A": {"name": "A"},
+				"B": {"name": "B"}
+			}"#,
+			)),
+			..ProxiesState::default()
+		};
+		let tabs = |state: &ProxiesState| -> Vec<String> {
+			state.providers()
+				.iter()
+				.map(|p| p.name.clone())
+				.collect()
+		};
+		state.cycle_tab_order();
+		state.cycle_tab_order();
+		assert_eq!(tabs(&state), ["Asia", "Europe"]);
+
+		state.record_delay("B".into(), 80, String::new());
+		assert_eq!(tabs(&state), ["Europe", "Asia"]);
+		// the tab moved with its group
+		assert_eq!(state.provider, 1);
+		assert_eq!(state.group_name().as_deref(), Some("Asia"));
+
+		state.record_delay("A".into(), 40, String::new());
+		assert_eq!(tabs(&state), ["Asia", "Europe"]);
+		assert_eq!(state.group_name().as_deref(), Some("Asia"));
+	}
+
+	#[test]
+	fn parses_wrapped_proxies() {
+		let body = format!(r#"{{"proxies": {}}}"#, PROXIES);
+		let proxies = parse_proxies(&body);
+		assert_eq!(proxies.len(), 3);
+		assert_eq!(proxies["GLOBAL"].now.as_deref(), Some("A"));
+	}
+
+	#[test]
+	fn parses_bare_proxies() {
+		let proxies = parse_proxies(PROXIES);
+		assert_eq!(proxies.len(), 3);
+		assert!(proxies["GLOBAL"].is_provider());
+	}
+
+	#[test]
+	fn bare_proxies_may_contain_a_node_named_proxies() {
+		let proxies =
+			parse_proxies(r#"{"proxies": {"name": "proxies"}}"#);
+		assert_eq!(proxies["proxies"].name, "proxies");
+	}
+
+	// Serves one response per connection from
+	// `handler(method, path, body)`.
+	fn mock_server<F>(handler: F) -> String
+	where
+		F: Fn(&str, &str, &str) -> (u16, String) + Send + 'static,
+	{
+		use std::io::{Read, Write};
+		use std::net::TcpListener;
+
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				let mut stream = match stream {
+					Ok(stream) => stream,
+					_ => break,
+				};
+				let mut reader = BufReader::new(&mut stream);
+				let mut head = String::new();
+				let mut length = 0;
+				loop {
+					let mut line = String::new();
+					if reader
+						.read_line(&mut line)
+						.unwrap_or(0) == 0 || line == "\r\n"
+					{
+						break;
+					}
+					let lower = line.to_ascii_lowercase();
+					if let Some(value) = lower
+						.strip_prefix("content-length:")
+					{
+						length = value
+							.trim()
+							.parse()
+							.unwrap_or(0);
+					}
+					head.push_str(&line);
+				}
+				let mut body = vec![0; length];
+				reader.read_exact(&mut body).ok();
+
+				let mut request = head.split_whitespace();
+				let method = request.next().unwrap_or_default();
+				let path = request.next().unwrap_or_default();
+				let body = String::from_utf8_lossy(&body);
+				let (status, body) =
+					handler(method, path, &body);
+				write!(
+					stream,
+					"HTTP/1.1 {} Mock\r\nContent-Length: {}\r\n\
+					 Connection: close\r\n\r\n{}",
+					status,
+					body.len(),
+					body
+				)
+				.ok();
+			}
+		});
+		url
+	}
+
+	#[test]
+	fn probe_tells_wrong_targets_from_unreachable_ones() {
+		let url = mock_server(|_, path, _| match path {
+			"/version" => {
+				(200, String::from(r#"{"version": "v1"}"#))
+			}
+			_ => (404, String::new()),
+		});
+		let version = |url: &str| {
+			let http = client(url);
+			runtime().block_on(http.version())
+		};
+		assert_eq!(version(&url).unwrap().to_string(), "v1");
+
+		// an HTTP proxy port answers, just not with JSON
+		let url = mock_server(|_, _, _| {
+			(400, String::from("Bad Request"))
+		});
+		let err = version(&url).unwrap_err();
+		assert!(err.contains("does not look like a Clash controller"));
+
+		let err = version("http://127.0.0.1:9").unwrap_err();
+		assert!(err.starts_with("Could not reach"), "{}", err);
+	}
+
+	#[test]
+	fn delay_tests_report_timeouts_as_zero() {
+		let url = mock_server(|_, path, _| {
+			assert!(path.starts_with(
+				"/proxies/HK%2001/delay?timeout=2000"
+			));
+			match path {
+				p if p.contains("fast") => {
+					(200, r#"{"delay": 50}"#.into())
+				}
+				p if p.contains("slow") => {
+					(200, r#"{"delay": 300}"#.into())
+				}
+				_ => (504, r#"{"message": "Timeout"}"#.into()),
+			}
+		});
+		let http = client(&url);
+		let urls = |names: &[&str]| -> Vec<String> {
+			names.iter().map(|n| format!("http://{}/", n)).collect()
+		};
+
+		let measure = |urls: &[String], strategy| {
+			runtime().block_on(measure(
+				&http, "HK 01", 2000, urls, strategy,
+			))
+		};
+
+		let all = urls(&["slow", "dead", "fast"]);
+		let best = measure(&all, TestStrategy::Best);
+		assert_eq!(best, (50, String::from("http://fast/")));
+		let median = measure(&all, TestStrategy::Median);
+		assert_eq!(median.0, 300);
+
+		let dead = urls(&["dead"]);
+		assert_eq!(measure(&dead, TestStrategy::RoundRobin).0, 0);
+	}
+
+	#[test]
+	fn endpoints_join_with_a_single_slash() {
+		let endpoint =
+			|base: &str, path: &str| client(base).endpoint(path);
+		assert_eq!(
+			endpoint("http://host:9090", "/configs"),
+			"http://host:9090/configs"
+		);
+		assert_eq!(
+			endpoint("http://host:9090/", "/configs"),
+			"http://host:9090/configs"
+		);
+		assert_eq!(
+			endpoint("http://host:9090//", "configs"),
+			"http://host:9090/configs"
+		);
+		assert_eq!(
+			endpoint("http://host/clash/", "/proxies/GLOBAL"),
+			"http://host/clash/proxies/GLOBAL"
+		);
+	}
+
+	#[test]
+	fn path_prefixes_are_kept() {
+		let paths = Arc::new(Mutex::new(Vec::new()));
+		let sent = paths.clone();
+		let url = mock_server(move |_, path, _| {
+			sent.lock().unwrap().push(path.to_owned());
+			(200, String::from(r#"{"mode": "rule"}"#))
+		});
+		let url =
+			normalize_url(&format!("{}/clash-api/", url)).unwrap();
+		let http = client(&url);
+		runtime().block_on(async {
+			assert!(http.configs().await.is_ok());
+			http.update_provider("sub a").await.ok();
+		});
+		assert_eq!(
+			*paths.lock().unwrap(),
+			[
+				"/clash-api/configs",
+				"/clash-api/providers/proxies/sub%20a"
+			]
+		);
+	}
+
+	#[test]
+	fn slow_controllers_time_out_with_a_clear_error() {
+		let url = mock_server(|_, _, _| {
+			thread::sleep(Duration::from_millis(1500));
+			(200, String::from(r#"{"proxies": {}}"#))
+		});
+		let timeout = Some(Duration::from_millis(500));
+		let http =
+			HttpClient::new(&url, USER_AGENT, None, false, timeout)
+				.unwrap();
+		let err = runtime().block_on(http.proxies()).unwrap_err();
+		assert_eq!(
+			http.describe(&*err),
+			"the controller did not answer within 0.5s"
+		);
+	}
+
+	#[test]
+	fn mutations_accept_no_content() {
+		let url = mock_server(|_, _, _| (204, String::new()));
+		let http = client(&url);
+		let patch = ConfigPatch {
+			mode: Some(Mode::Rule),
+			..ConfigPatch::default()
+		};
+		runtime().block_on(async {
+			assert!(http
+				.update_proxy("GLOBAL", "B")
+				.await
+				.is_ok_and(|(status, _)| status.is_success()));
+			assert!(http.update_config(&patch).await.is_ok());
+		});
+
+		let url = mock_server(|_, _, _| (400, String::new()));
+		let http = client(&url);
+		runtime().block_on(async {
+			assert_eq!(
+				http.update_proxy("GLOBAL", "B")
+					.await
+					.unwrap()
+					.0,
+				StatusCode::BAD_REQUEST
+			);
+			assert!(http.update_config(&patch).await.is_err());
+		});
+	}
+
+	#[test]
+	fn selecting_in_the_third_group_keeps_the_cursor() {
+		let now = Arc::new(Mutex::new(String::from("n1")));
+		let shared = now.clone();
+		let url = mock_server(move |method, _, body| {
+			let mut now = shared.lock().unwrap();
+			if method == "PUT" {
+				let body: HashMap<String, String> =
+					serde_json::from_str(body).unwrap();
+				*now = body["name"].clone();
+				return (204, String::new());
+			}
+			let group = |name: &str, now: &str, all: &str| {
+				format!(
+					r#""{0}": {{"name": "{0}", "now": "{1}",
+					"all": [{2}]}}"#,
+					name, now, all
+				)
+			};
+			// shorter first groups, where a reset cursor would land
+			let body = format!(
+				"{{{}, {}, {}}}",
+				group("Alpha", "n1", r#""n1""#),
+				group("Beta", "n1", r#""n1", "n2""#),
+				group(
+					"Gamma",
+					&now,
+					r#""n1", "n2", "n3", "n4""#
+				)
+			);
+			(200, body)
+		});
+
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.fetch_proxies();
+		receive_until(&mut app, |app| {
+			app.proxies_state.proxies.is_some()
+		});
+		app.proxies_state.next_tab();
+		app.proxies_state.next_tab();
+		app.proxies_state.next_proxy();
+		app.proxies_state.next_proxy();
+		app.select_proxy();
+		receive_until(&mut app, |app| app.status.is_some());
+
+		assert_eq!(*now.lock().unwrap(), "n3");
+		assert_eq!(app.proxies_state.provider, 2);
+		assert_eq!(app.proxies_state.proxy_index, 2);
+		assert_eq!(app.proxies_state.proxies_len, 4);
+		assert_eq!(
+			app.proxies_state.highlighted(),
+			Some(("Gamma", "n3"))
+		);
+	}
+
+	#[test]
+	fn global_node_is_switched_from_the_general_page() {
+		let now = Arc::new(Mutex::new(String::from("A")));
+		let shared = now.clone();
+		let url = mock_server(move |method, path, body| {
+			let mut now = shared.lock().unwrap();
+			if method == "PUT" {
+				assert_eq!(path, "/proxies/GLOBAL");
+				let body: HashMap<String, String> =
+					serde_json::from_str(body).unwrap();
+				*now = body["name"].clone();
+				return (204, String::new());
+			}
+			let body = PROXIES.replace(
+				r#""now": "A""#,
+				&format!(r#""now": "{}""#, now),
+			);
+			(200, body)
+		});
+
+		// loaded on demand, as with --mode-only
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.focus = Pane::General;
+		process_key(KeyCode::Char('p'), &mut app);
+		assert!(app.modal.is_none());
+		receive_until(&mut app, |app| app.modal.is_some());
+		assert!(matches!(app.modal, Some(Modal::Global(0))));
+
+		process_key(KeyCode::Char('j'), &mut app);
+		process_key(KeyCode::Enter, &mut app);
+		assert!(app.modal.is_none());
+		receive_until(&mut app, |app| app.status.is_some());
+		assert_eq!(*now.lock().unwrap(), "B");
+		assert_eq!(app.proxies_state.now(GLOBAL), Some("B"));
+		assert_eq!(status_text(&app), Some("GLOBAL now uses B"));
+
+		// the cursor opens on the node in use
+		process_key(KeyCode::Char('p'), &mut app);
+		assert!(matches!(app.modal, Some(Modal::Global(1))));
+	}
+
+	fn status_text(app: &App) -> Option<&str> {
+		match &app.status {
+			Some(Status::Info(text) | Status::Error(text)) => {
+				Some(text)
+			}
+			_ => None,
+		}
+	}
+
+	#[test]
+	fn selection_that_does_not_land_is_reported() {
+		use std::sync::atomic::AtomicUsize;
+
+		let puts = Arc::new(AtomicUsize::new(0));
+		let counter = puts.clone();
+		let url = mock_server(move |method, _, _| match method {
+			"PUT" => {
+				counter.fetch_add(1, Ordering::SeqCst);
+				(204, String::new())
+			}
+			_ => (200, PROXIES.to_owned()),
+		});
+
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.fetch_proxies();
+		receive_until(&mut app, |app| {
+			app.proxies_state.proxies.is_some()
+		});
+		app.proxies_state.next_proxy();
+		app.select_proxy();
+		receive_until(&mut app, |app| app.status.is_some());
+
+		assert_eq!(puts.load(Ordering::SeqCst), 2);
+		assert_eq!(
+			status_text(&app),
+			Some("GLOBAL did not switch to B, still on A")
+		);
+		assert!(app.store.recent.is_empty());
+		assert_eq!(app.proxies_state.proxy_index, 1);
+	}
+
+	#[test]
+	fn page_fetches_arrive_as_messages() {
+		// the controller answers once the key press was handled
+		let (answer, answered) = mpsc::channel::<()>();
+		let answered = Mutex::new(answered);
+		let url = mock_server(move |_, path, _| {
+			answered.lock().unwrap().recv().ok();
+			match path {
+				"/proxies" => (200, PROXIES.to_owned()),
+				_ => (404, String::new()),
+			}
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		let page = app.routes.iter().position(|r| *r == Route::Proxies);
+		let key = char::from_digit(page.unwrap() as u32 + 1, 10);
+		process_key(KeyCode::Char(key.unwrap()), &mut app);
+		assert!(app.rx.try_recv().is_err());
+		assert!(app.proxies_state.proxies.is_none());
+
+		answer.send(()).unwrap();
+		receive_until(&mut app, |app| {
+			app.proxies_state.proxies.is_some()
+		});
+		assert_eq!(app.proxies_state.providers_len, 1);
+	}
+
+	#[test]
+	fn profiles_skip_nodes_that_are_gone_without_asking() {
+		let puts = Arc::new(Mutex::new(Vec::new()));
+		let sent = puts.clone();
+		let url = mock_server(move |method, path, _| {
+			if method == "PUT" {
+				sent.lock().unwrap().push(path.to_owned());
+				return (204, String::new());
+			}
+			match path {
+				"/proxies" => (200, PROXIES.to_owned()),
+				_ => (200, String::from(r#"{"mode": "rule"}"#)),
+			}
+		});
+		let mut store = Store::default();
+		store.save_profile(Profile {
+			name: String::from("home"),
+			mode: None,
+			selections: [("GLOBAL", "B"), ("Gone", "A")]
+				.into_iter()
+				.map(|(g, n)| (g.to_owned(), n.to_owned()))
+				.collect(),
+		});
+		let _runtime = runtime().enter();
+		let mut app =
+			App::new(&url, None, &ConfigFile::default(), store)
+				.unwrap();
+
+		app.apply_profile(0);
+		receive_until(&mut app, |app| app.status.is_some());
+		assert_eq!(*puts.lock().unwrap(), ["/proxies/GLOBAL"]);
+		match &app.status {
+			Some(Status::Error(text)) => assert_eq!(
+				text,
+				"Applied profile home, skipped Gone → A"
+			),
+			_ => panic!("no error"),
+		}
+	}
+
+	#[test]
+	fn dns_queries_show_the_records_or_why_not() {
+		let url = mock_server(|_, path, _| match path {
+			"/dns/query?name=example.com&type=AAAA" => (
+				200,
+				String::from(
+					r#"{"Status": 0, "Answer": [{"name": "example.com.",
+					"type": 28, "TTL": 300, "data": "2606:2800::1"}]}"#,
+				),
+			),
+			_ => (200, String::from(r#"{"Status": 3}"#)),
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.submit_prompt(
+			Prompt::DnsQuery,
+			String::from("example.com aaaa"),
+		);
+		receive(&mut app);
+		let answer = match &app.modal {
+			Some(Modal::Dns(DnsLookup {
+				result: Some(Ok(answer)),
+				..
+			})) => answer,
+			_ => panic!("no answer"),
+		};
+		assert_eq!(answer.answer[0].kind_name(), "AAAA");
+		assert_eq!(answer.answer[0].data, "2606:2800::1");
+
+		let http = client(&url);
+		let answer = runtime()
+			.block_on(http.dns_query("nowhere.invalid", "A"))
+			.unwrap();
+		assert!(answer.answer.is_empty());
+		assert_eq!(answer.status_name(), "NXDOMAIN");
+
+		let url = mock_server(|_, _, _| (404, String::new()));
+		let http = client(&url);
+		let err = runtime()
+			.block_on(http.dns_query("example.com", "A"))
+			.unwrap_err();
+		assert!(err.starts_with("DNS API not available"));
+	}
+
+	#[test]
+	fn last_page_and_tab_are_restored() {
+		let url = mock_server(|_, path, _| match path {
+			"/proxies" => (
+				200,
+				String::from(
+					r#"{"A": {"name": "A", "all": ["N"]},
+					"B": {"name": "B", "all": ["N"]},
+					"N": {"name": "N"}}"#,
+				),
+			),
+			_ => (404, String::new()),
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.store.last_route = Some(Route::Proxies);
+		app.store.last_group = Some(String::from("B"));
+		app.restore_position(None);
+		assert_eq!(app.route(), Some(&Route::Proxies));
+
+		app.fetch_data();
+		receive_until(&mut app, |app| {
+			app.proxies_state.proxies.is_some()
+		});
+		assert_eq!(app.proxies_state.highlighted(), Some(("B", "N")));
+
+		// --start-page wins, a route missing from the menu is ignored
+		app.routes.retain(|r| *r != Route::Rules);
+		app.restore_position(Some(Route::Logs));
+		assert_eq!(app.route(), Some(&Route::Logs));
+		app.restore_position(Some(Route::Rules));
+		assert_eq!(app.route(), Some(&Route::Logs));
+	}
+
+	#[test]
+	fn closing_every_connection_asks_first() {
+		let deletes = Arc::new(Mutex::new(0));
+		let sent = deletes.clone();
+		let url = mock_server(move |method, _, _| {
+			if method == "DELETE" {
+				*sent.lock().unwrap() += 1;
+			}
+			(200, String::from(r#"{"connections": []}"#))
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.focus = Pane::Connections;
+
+		process_key(KeyCode::Char('D'), &mut app);
+		assert!(matches!(
+			app.modal,
+			Some(Modal::Confirm(Action::CloseConnections))
+		));
+		// any other key cancels
+		process_key(KeyCode::Char('j'), &mut app);
+		assert!(app.modal.is_none());
+		assert_eq!(*deletes.lock().unwrap(), 0);
+
+		process_key(KeyCode::Char('D'), &mut app);
+		process_key(KeyCode::Char('y'), &mut app);
+		assert!(app.modal.is_none());
+		receive_until(&mut app, |app| app.status.is_some());
+		assert_eq!(*deletes.lock().unwrap(), 1);
+		assert_eq!(status_text(&app), Some("Closed all connections"));
+	}
+
+	#[test]
+	fn searched_connections_close_by_id() {
+		let deleted = Arc::new(Mutex::new(Vec::new()));
+		let sent = deleted.clone();
+		let url = mock_server(move |method, path, _| {
+			if method == "DELETE" {
+				sent.lock().unwrap().push(path.to_owned());
+			}
+			(200, String::from(r#"{"connections": []}"#))
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.focus = Pane::Connections;
+		let connections = serde_json::from_str(
+			r#"[
+			{"id": "c1", "upload": 1, "download": 2,
+				"metadata": {"host": "a.example.com"}},
+			{"id": "c2", "upload": 3, "download": 4,
+				"metadata": {"destinationIP": "10.0.0.2"}},
+			{"id": "c3", "upload": 5, "download": 6,
+				"metadata": {"host": "b.example.com"}}
+		]"#,
+		)
+		.unwrap();
+		app.connections_state.receive(Some(connections));
+
+		process_key(KeyCode::Char('/'), &mut app);
+		for c in "B.EX".chars() {
+			process_key(KeyCode::Char(c), &mut app);
+		}
+		// Esc goes back to the last search, none here
+		process_key(KeyCode::Esc, &mut app);
+		assert_eq!(app.connections_state.visible().len(), 3);
+
+		process_key(KeyCode::Char('/'), &mut app);
+		for c in "10.0".chars() {
+			process_key(KeyCode::Char(c), &mut app);
+		}
+		process_key(KeyCode::Enter, &mut app);
+		let visible: Vec<_> = app
+			.connections_state
+			.visible()
+			.iter()
+			.map(|c| c.id.clone())
+			.collect();
+		assert_eq!(visible, ["c2"]);
+		assert_eq!(
+			connections_title(&app.connections_state, 1),
+			"Connections (1 of 3) \"10.0\""
+		);
+
+		process_key(KeyCode::Char('d'), &mut app);
+		receive_until(&mut app, |app| app.status.is_some());
+		assert_eq!(*deleted.lock().unwrap(), ["/connections/c2"]);
+	}
+
+	#[test]
+	fn connections_sort_by_traffic_or_age() {
+		let mut state = ConnectionsState::default();
+		let connections = serde_json::from_str(
+			r#"[
+			{"id": "a", "upload": 30, "download": 10,
+				"start": "2024-05-01T08:00:00.5+08:00", "metadata": {}},
+			{"id": "b", "upload": 10, "download": 20,
+				"start": "2024-05-01T00:30:00Z", "metadata": {}},
+			{"id": "c", "upload": 20, "download": 30,
+				"start": "2024-05-01T00:10:00.123456Z", "metadata": {}}
+		]"#,
+		)
+		.unwrap();
+		state.receive(Some(connections));
+		let order = |state: &ConnectionsState| -> String {
+			state.visible().iter().map(|c| c.id.as_str()).collect()
+		};
+		assert_eq!(order(&state), "abc");
+
+		state.next();
+		state.cycle_sort();
+		assert_eq!(order(&state), "cba");
+		// the cursor stays on b
+		assert_eq!(state.index, 1);
+		state.cycle_sort();
+		assert_eq!(order(&state), "acb");
+		state.cycle_sort();
+		assert_eq!(order(&state), "acb");
+		state.reverse_sort();
+		assert_eq!(order(&state), "bca");
+		assert_eq!(state.selected().unwrap().id, "b");
+		state.cycle_sort();
+		assert_eq!(order(&state), "abc");
+	}
+
+	#[test]
+	fn timestamps_parse_to_unix_seconds() {
+		assert_eq!(parse_timestamp("1970-01-01T00:00:00Z"), Some(0));
+		assert_eq!(
+			parse_timestamp("2024-05-01T08:30:00.123+08:00"),
+			Some(1714523400)
+		);
+		assert_eq!(
+			parse_timestamp("2024-02-29T23:59:59-01:00"),
+			Some(1709254799)
+		);
+		assert_eq!(parse_timestamp(""), None);
+		assert_eq!(parse_timestamp("2024-05-01T08:30"), None);
+		assert_eq!(format_duration(59), "59s");
+		assert_eq!(format_duration(3599), "59m 59s");
+		assert_eq!(format_duration(86399), "23h 59m");
+		assert_eq!(format_duration(2 * 86400 + 4 * 3600), "2d 04h");
+		assert_eq!(format_duration(1018 * 86400 + 3600), "1018d");
+	}
+
+	#[test]
+	fn controller_urls_get_a_scheme_and_lose_the_trailing_slash() {
+		assert_eq!(
+			normalize_url("localhost:9090").unwrap(),
+			"http://localhost:9090"
+		);
+		assert_eq!(
+			normalize_url(" https://router.lan/api/ ").unwrap(),
+			"https://router.lan/api"
+		);
+		assert_eq!(
+			normalize_url("192.168.1.1:9090/").unwrap(),
+			"http://192.168.1.1:9090"
+		);
+		for bad in ["", "ftp://host", "http://", "http://a b", "::"] {
+			let err = normalize_url(bad).unwrap_err();
+			assert!(
+				err.starts_with("Not a controller URL"),
+				"{}",
+				bad
+			);
+		}
+	}
+
+	#[test]
+	fn byte_counts_round_to_one_decimal() {
+		assert_eq!(format_bytes(0), "0 B");
+		assert_eq!(format_bytes(1023), "1023 B");
+		assert_eq!(format_bytes(1024), "1.0 KB");
+		assert_eq!(format_bytes(1536), "1.5 KB");
+		assert_eq!(format_bytes(1024 * 1024 - 1), "1.0 MB");
+		assert_eq!(format_bytes(1024 * 1024), "1.0 MB");
+		assert_eq!(format_bytes(3 << 30), "3.0 GB");
+		assert_eq!(format_bytes(5 << 40), "5.0 TB");
+		// nothing above TB
+		assert_eq!(format_bytes(2048 << 40), "2048.0 TB");
+		assert_eq!(format_bytes(u64::MAX), "16777216.0 TB");
+		assert_eq!(format_speed(0), "0 B/s");
+		assert_eq!(format_speed(1024), "1.0 KB/s");
+	}
+
+	#[test]
+	fn slow_fetches_show_a_spinner_until_they_answer() {
+		let url = mock_server(|_, _, _| {
+			thread::sleep(LOADING_DELAY * 2);
+			(200, String::from(r#"{"rules": []}"#))
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		let page = app.routes.iter().position(|r| *r == Route::Rules);
+		app.navigate(page.unwrap());
+		let loading = |app: &mut App| {
+			screen_lines(|f| render(f, app))[0].contains("loading")
+		};
+		assert!(!loading(&mut app));
+
+		thread::sleep(LOADING_DELAY);
+		assert!(loading(&mut app));
+		receive(&mut app);
+		assert!(!loading(&mut app));
+	}
+
+	#[test]
+	fn traffic_keeps_a_minute_of_samples() {
+		let mut state = TrafficState::default();
+		assert_eq!(state.text(), "↑ -  ↓ -");
+		for i in 0..100 {
+			state.push(Traffic {
+				up: i,
+				down: i * 2048,
+			});
+		}
+		assert_eq!(state.history.len(), TRAFFIC_SAMPLES);
+		assert_eq!(state.history[0].up, 40);
+		assert_eq!(state.text(), "↑ 99 B/s  ↓ 198.0 KB/s");
+
+		state.status = StreamStatus::Unreachable;
+		assert_eq!(state.text(), "traffic unavailable");
+	}
+
+	#[test]
+	fn failed_fetches_stay_in_the_footer_until_one_succeeds() {
+		let down = Arc::new(AtomicBool::new(true));
+		let flag = down.clone();
+		let url = mock_server(move |_, _, _| {
+			if flag.load(Ordering::SeqCst) {
+				(500, String::new())
+			} else {
+				(200, PROXIES.to_owned())
+			}
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		let page = app.routes.iter().position(|r| *r == Route::Proxies);
+
+		app.navigate(page.unwrap());
+		receive(&mut app);
+		let error = app.fetch_error.clone().unwrap();
+		assert!(error.starts_with("Could not load the proxies: "));
+		assert!(error.contains("500"));
+		let lines = screen_lines(|f| render(f, &mut app));
+		assert!(lines[11].starts_with("Could not load the proxies"));
+
+		// a key press clears the status, not this
+		process_key(KeyCode::Null, &mut app);
+		assert!(app.fetch_error.is_some());
+
+		down.store(false, Ordering::SeqCst);
+		app.fetch_data();
+		receive(&mut app);
+		assert!(app.fetch_error.is_none());
+		assert!(app.proxies_state.proxies.is_some());
+	}
+
+	fn screen_lines(
+		draw: impl FnOnce(&mut Frame<tui::backend::TestBackend>),
+	) -> Vec<String> {
+		let backend = tui::backend::TestBackend::new(60, 12);
+		let mut terminal = Terminal::new(backend).unwrap();
+		terminal.draw(draw).unwrap();
+		let buffer = terminal.backend().buffer();
+		(0..buffer.area.height)
+			.map(|y| {
+				(0..buffer.area.width)
+					.map(|x| {
+						buffer.get(x, y).symbol.as_str()
+					})
+					.collect()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn general_renders_with_only_one_of_configs_and_proxies() {
+		let mut app = proxies_app();
+		let lines = screen_lines(|f| {
+			render_general(
+				f,
+				&Theme::default(),
+				&mut app.general_state,
+				&app.proxies_state,
+				&app.focus,
+				false,
+				f.size(),
+			)
+		});
+		assert!(lines[0].contains("General (mode not loaded)"));
+		assert!(lines.iter().any(|l| l.contains("GLOBAL → A")));
+
+		app.general_state.config = Some(Config {
+			mode: Mode::from(String::from("Global")),
+			allow_lan: None,
+			log_level: None,
+		});
+		app.proxies_state.proxies = None;
+		let lines = screen_lines(|f| {
+			render_general(
+				f,
+				&Theme::default(),
+				&mut app.general_state,
+				&app.proxies_state,
+				&app.focus,
+				false,
+				f.size(),
+			)
+		});
+		assert!(lines[0].contains("General─"));
+		assert!(lines
+			.iter()
+			.any(|l| l.contains("Selections not loaded")));
+	}
+
+	fn hint_text(app: &App) -> Option<&str> {
+		app.hint.as_ref().map(|(text, _)| text.as_str())
+	}
+
+	#[test]
+	fn hints_show_once_per_pane() {
+		let _runtime = runtime().enter();
+		let mut app = app("http://127.0.0.1:9");
+		app.show_hint();
+		assert_eq!(
+			hint_text(&app),
+			Some("j/k move, l open, 1-6 jump, q quit")
+		);
+
+		// the next key press clears it, a new pane shows its own
+		app.focus = Pane::Rules;
+		process_key(KeyCode::Char('f'), &mut app);
+		assert_eq!(
+			hint_text(&app),
+			Some("m match host, f filter, e export, j/k scroll")
+		);
+		process_key(KeyCode::Char('f'), &mut app);
+		assert_eq!(hint_text(&app), None);
+
+		app.focus = Pane::Menu;
+		process_key(KeyCode::Char('j'), &mut app);
+		assert_eq!(hint_text(&app), None);
+
+		let config = ConfigFile {
+			hints: Some(false),
+			..Default::default()
+		};
+		let mut app = App::new(
+			"http://127.0.0.1:9",
+			None,
+			&config,
+			Store::default(),
+		)
+		.unwrap();
+		app.show_hint();
+		assert!(app.hint.is_none());
+	}
+
+	#[test]
+	fn columns_move_a_column_at_a_time() {
+		let mut state = ProxiesState {
+			proxies_len: 5,
+			..ProxiesState::default()
+		};
+		// drawn as a single list
+		state.next_column();
+		assert_eq!(state.proxy_index, 0);
+
+		state.grid_rows = 2;
+		state.next_proxy();
+		state.next_column();
+		assert_eq!(state.proxy_index, 3);
+		// no node to the right of the second row
+		state.next_column();
+		assert_eq!(state.proxy_index, 3);
+		state.previous_column();
+		state.previous_column();
+		assert_eq!(state.proxy_index, 1);
+	}
+
+	#[test]
+	fn subscription_dates_and_warnings() {
+		assert_eq!(format_date(0), "1970-01-01");
+		assert_eq!(format_date(951782400), "2000-02-29");
+		assert_eq!(format_date(1735689599), "2024-12-31");
+
+		let day = 86400;
+		let info = |used, total, expire| SubscriptionInfo {
+			download: used,
+			total,
+			expire,
+			..Default::default()
+		};
+		let warning = |info: SubscriptionInfo| {
+			info.warning(100 * day).map(|(text, _)| text)
+		};
+		assert_eq!(warning(info(5, 10, 0)), None);
+		assert_eq!(warning(info(5, 0, 200 * day)), None);
+		assert_eq!(warning(info(10, 10, 0)), Some("over quota"));
+		assert_eq!(
+			warning(info(5, 10, 103 * day)),
+			Some("expires soon")
+		);
+		assert_eq!(warning(info(50, 10, 99 * day)), Some("expired"));
+	}
+
+	#[test]
+	fn provider_health_counts_checked_nodes() {
+		let provider = |proxies: &str| {
+			let body = format!(
+				r#"{{"name": "sub", "vehicleType": "HTTP",
+				"proxies": [{}]}}"#,
+				proxies
+			);
+			serde_json::from_str::<ProxyProvider>(&body).unwrap()
+		};
+		assert_eq!(provider("").alive(), None);
+		assert_eq!(provider(r#"{"name": "A"}"#).alive(), None);
+		let checked = provider(
+			r#"{"name": "A", "history": [{"time": "", "delay": 90}]},
+			{"name": "B", "history": [{"time": "", "delay": 0}]},
+			{"name": "C"}"#,
+		);
+		assert_eq!(checked.alive(), Some((1, 3)));
+	}
+
+	#[test]
+	fn tab_window_keeps_the_selected_tab_in_view() {
+		let widths = [10; 8];
+		// everything fits
+		assert_eq!(tab_window(&widths, 3, 104), (0, 8));
+		// 13 columns per tab, 40 after the arrows: three tabs
+		assert_eq!(tab_window(&widths, 0, 48), (0, 3));
+		assert_eq!(tab_window(&widths, 4, 48), (2, 5));
+		assert_eq!(tab_window(&widths, 7, 48), (5, 8));
+		// too narrow for even the selected tab
+		assert_eq!(tab_window(&widths, 5, 4), (5, 6));
+	}
+
+	#[test]
+	fn sort_orders_cycle_and_keep_the_controllers_order() {
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(
+				r#"{
+				"G": {"name": "G", "all": ["D", "C", "B", "A"]},
+				"A": {"name": "A", "history": [{"delay": 300}]},
+				"B": {"name": "B", "history": [{"delay": 0}]},
+				"C": {"name": "C", "history": [{"delay": 100}]},
+				"D": {"name": "D"}
+			}"#,
+			)),
+			providers_len: 1,
+			proxies_len: 4,
+			..ProxiesState::default()
+		};
+		let mut orders = Vec::new();
+		for _ in 0..5 {
+			state.cycle_sort();
+			let group = state.providers()[0];
+			orders.push((state.sort, state.nodes(group).join("")));
+		}
+		assert_eq!(
+			orders,
+			[
+				(
+					Some(SortOrder::Alphabetical),
+					String::from("ABCD")
+				),
+				// untested and timed out nodes last
+				(
+					Some(SortOrder::Latency),
+					String::from("CABD")
+				),
+				(
+					Some(SortOrder::Original),
+					String::from("DCBA")
+				),
+				(Some(SortOrder::Pinned), String::from("ABCD")),
+				(None, String::from("ABCD")),
+			]
+		);
+	}
+
+	#[test]
+	fn recently_tested_groups_are_not_auto_tested() {
+		let _runtime = runtime().enter();
+		let mut app = proxies_app();
+		app.auto_test = true;
+		let enter = |app: &mut App| {
+			app.status = None;
+			app.schedule_auto_test();
+			if let Some((_, since)) = &mut app.auto_test_tab {
+				*since -= AUTO_TEST_DELAY;
+			}
+			app.on_tick();
+			app.status.is_some()
+		};
+		assert!(enter(&mut app));
+		assert!(!enter(&mut app));
+
+		app.group_tested
+			.values_mut()
+			.for_each(|at| *at -= AUTO_TEST_TTL);
+		assert!(enter(&mut app));
+	}
+
+	#[test]
+	fn auto_groups_are_marked_and_report_rejected_selections() {
+		let url = mock_server(|method, _, _| match method {
+			"PUT" => (
+				400,
+				String::from(
+					r#"{"message": "Must be a Selector"}"#,
+				),
+			),
+			_ => (200, String::from(r#"{"proxies": {}}"#)),
+		});
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(
+				r#"{
+				"Auto": {"name": "Auto", "type": "URLTest",
+					"all": ["A", "B"], "now": "A"},
+				"A": {"name": "A"},
+				"B": {"name": "B"}
+			}"#,
+			)),
+			providers_len: 1,
+			proxies_len: 2,
+			..ProxiesState::default()
+		};
+		let lines = screen_lines(|f| {
+			render_proxies(
+				f,
+				&Theme::default(),
+				&mut state,
+				&Pane::Proxies,
+				false,
+				f.size(),
+			)
+		});
+		assert!(
+			lines[1].contains("Auto (URLTest) → A"),
+			"{}",
+			lines[1]
+		);
+
+		state.next_proxy();
+		let http = client(&url);
+		let (group, node) = state.selection().unwrap();
+		let auto = state.auto_kind(&group);
+		let (res, _) =
+			runtime().block_on(select(&http, &group, &node, auto));
+		assert_eq!(
+			res.unwrap_err(),
+			"Cannot select on URLTest group Auto: \
+			 Must be a Selector"
+		);
+	}
+
+	#[test]
+	fn rejected_selection_shows_the_controller_message() {
+		let url = mock_server(|method, _, _| match method {
+			"PUT" => (
+				400,
+				String::from(
+					r#"{"message": "Must be a Selector"}"#,
+				),
+			),
+			_ => (200, PROXIES.to_owned()),
+		});
+
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.fetch_proxies();
+		receive_until(&mut app, |app| {
+			app.proxies_state.proxies.is_some()
+		});
+		app.proxies_state.next_proxy();
+		app.select_proxy();
+		receive_until(&mut app, |app| app.status.is_some());
+
+		assert_eq!(
+			status_text(&app),
+			Some("Could not select B in GLOBAL: Must be a Selector")
+		);
+	}
+
+	#[test]
+	fn long_lists_show_the_position_within_all_items() {
+		let nodes: Vec<_> =
+			(0..30).map(|i| format!("\"N{:02}\"", i)).collect();
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(&format!(
+				r#"{{"G": {{"name": "G", "all": [{}]}}}}"#,
+				nodes.join(", ")
+			))),
+			providers_len: 1,
+			proxies_len: 30,
+			proxy_index: 12,
+			..ProxiesState::default()
+		};
+		let lines = screen_lines(|f| {
+			render_proxies(
+				f,
+				&Theme::default(),
+				&mut state,
+				&Pane::Proxies,
+				false,
+				f.size(),
+			)
+		});
+		assert!(lines[11].ends_with(" 13/30 ─┘"), "{}", lines[11]);
+
+		let rules: Vec<Rule> = serde_json::from_str(&format!(
+			"[{}]",
+			vec![
+				r#"{"type": "Match", "payload": "", "proxy": "P"}"#;
+				40
+			]
+			.join(", ")
+		))
+		.unwrap();
+		let mut state = RulesState {
+			rules: Some(rules),
+			offset: 39,
+			..RulesState::default()
+		};
+		let lines = screen_lines(|f| {
+			render_rules(
+				f,
+				&Theme::default(),
+				&mut state,
+				&ProxiesState::default(),
+				&Pane::Rules,
+				f.size(),
+			)
+		});
+		assert!(lines[11].ends_with(" 40/40 ─┘"), "{}", lines[11]);
+	}
+
+	#[test]
+	fn minimum_log_level_hides_entries_without_dropping_them() {
+		let mut state = LogsState {
+			status: StreamStatus::Connected,
+			..LogsState::default()
+		};
+		for level in ["debug", "info", "warning", "error", "trace"] {
+			state.push(LogEntry {
+				level: level.to_owned(),
+				payload: String::from("x"),
+			});
+		}
+		let shown = |state: &LogsState| -> Vec<_> {
+			state.filtered().map(|e| e.level.clone()).collect()
+		};
+
+		state.next_min_level();
+		assert_eq!(
+			shown(&state),
+			["info", "warning", "error", "trace"]
+		);
+		assert_eq!(state.title(), "Logs (info and up)");
+		state.next_min_level();
+		state.next_min_level();
+		assert_eq!(shown(&state), ["error", "trace"]);
+		assert_eq!(state.entries.len(), 5);
+
+		state.next_min_level();
+		assert_eq!(shown(&state).len(), 5);
+		assert_eq!(state.title(), "Logs");
+
+		assert_eq!(log_style("error").fg, Some(Color::Red));
+		assert_eq!(log_style("warning").fg, Some(Color::Yellow));
+		assert_eq!(log_style("info"), Style::default());
+	}
+
+	#[test]
+	fn rendered_node_is_the_selected_node() {
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(
+				r#"{
+				"G": {"name": "G", "all": ["C", "A", "B", "D"]},
+				"A": {"name": "A", "history": [{"delay": 300}]},
+				"B": {"name": "B", "history": [{"delay": 0}]},
+				"C": {"name": "C", "history": [{"delay": 100}]},
+				"D": {"name": "D"}
+			}"#,
+			)),
+			sort: Some(SortOrder::Latency),
+			providers_len: 1,
+			proxies_len: 4,
+			..ProxiesState::default()
+		};
+
+		let mut selected = Vec::new();
+		for _ in 0..state.proxies_len {
+			let (_, node) = state.highlighted().unwrap();
+			let node = node.to_owned();
+			let lines = screen_lines(|f| {
+				render_proxies(
+					f,
+					&Theme::default(),
+					&mut state,
+					&Pane::Proxies,
+					true,
+					f.size(),
+				)
+			});
+			// the accessible marker shows where the cursor is
+			let row = lines[4..]
+				.iter()
+				.map(|l| {
+					l.trim_matches(|c| c == '│' || c == ' ')
+				})
+				.find(|l| l.starts_with('>'))
+				.unwrap();
+			assert!(
+				row.ends_with(&format!(" {}", node)),
+				"{}",
+				row
+			);
+			selected.push(node);
+			state.next_proxy();
+		}
+		assert_eq!(selected, ["C", "A", "B", "D"]);
+	}
+
+	#[test]
+	fn node_list_scrolls_only_at_the_edges() {
+		let nodes: Vec<_> =
+			(0..20).map(|i| format!("N{:02}", i)).collect();
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(&format!(
+				r#"{{"G": {{"name": "G", "all": {:?}}}}}"#,
+				nodes
+			))),
+			providers_len: 1,
+			proxies_len: nodes.len(),
+			..ProxiesState::default()
+		};
+		let first_row = |state: &mut ProxiesState| {
+			let lines = screen_lines(|f| {
+				let rect = f.size();
+				render_proxies(
+					f,
+					&Theme::default(),
+					state,
+					&Pane::Proxies,
+					false,
+					rect,
+				)
+			});
+			lines[4].trim_matches(|c| c == '│' || c == ' ')
+				.to_owned()
+		};
+		assert!(first_row(&mut state).ends_with("N00"));
+
+		// seven rows fit, the view stays put until the cursor leaves it
+		for _ in 0..6 {
+			state.next_proxy();
+		}
+		assert!(first_row(&mut state).ends_with("N00"));
+		state.next_proxy();
+		assert!(first_row(&mut state).ends_with("N01"));
+		for _ in 0..3 {
+			state.previous_proxy();
+		}
+		assert!(first_row(&mut state).ends_with("N01"));
+	}
+
+	#[test]
+	fn selected_tab_names_the_node_in_use() {
+		let nodes: Vec<_> =
+			(0..20).map(|i| format!("N{:02}", i)).collect();
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(&format!(
+				r#"{{"G": {{"name": "G", "all": {:?}, "now": "N00"}},
+				"H": {{"name": "H", "all": ["N01"], "now": "N01"}}}}"#,
+				nodes
+			))),
+			providers_len: 2,
+			proxies_len: nodes.len(),
+			..ProxiesState::default()
+		};
+		for _ in 0..15 {
+			state.next_proxy();
+		}
+		let lines = screen_lines(|f| {
+			let rect = f.size();
+			render_proxies(
+				f,
+				&Theme::default(),
+				&mut state,
+				&Pane::Proxies,
+				false,
+				rect,
+			)
+		});
+		assert!(lines[1].contains("G → N00"), "{}", lines[1]);
+		assert!(!lines[1].contains("H → N01"), "{}", lines[1]);
+		assert!(!lines[4..].iter().any(|l| l.contains("N00")));
+	}
+
+	#[test]
+	fn menu_is_as_wide_as_its_entries_or_collapses() {
+		let _runtime = runtime().enter();
+		let mut app = proxies_app();
+		app.focus = Pane::Menu;
+		app.navigate(1);
+		let lines = screen_lines(|f| render(f, &mut app));
+		// "Clash (disconnected)" and the borders
+		assert!(lines[0].starts_with("┌Clash (disconnected)┐┌"));
+
+		process_key(KeyCode::Char('z'), &mut app);
+		let lines = screen_lines(|f| render(f, &mut app));
+		assert!(
+			lines[0].starts_with(
+				"Clash (disconnected) › Proxies › Proxies"
+			),
+			"{}",
+			lines[0]
+		);
+		assert!(lines[1].starts_with("┌Proxies"));
+	}
+
+	#[test]
+	fn number_keys_past_a_short_menu_do_nothing() {
+		let config = ConfigFile {
+			menu: vec![MenuSection {
+				title: String::from("Pages"),
+				routes: vec![
+					Route::General,
+					Route::Rules,
+					Route::Logs,
+				],
+			}],
+			..ConfigFile::default()
+		};
+		let _runtime = runtime().enter();
+		let mut app = App::new(
+			"http://127.0.0.1:9",
+			None,
+			&config,
+			Store::default(),
+		)
+		.unwrap();
+		process_key(KeyCode::Char('3'), &mut app);
+		assert_eq!(app.route(), Some(&Route::Logs));
+		process_key(KeyCode::Char('5'), &mut app);
+		assert_eq!(app.route(), Some(&Route::Logs));
+		process_key(KeyCode::Char('1'), &mut app);
+		assert_eq!(app.route(), Some(&Route::General));
+	}
+
+	#[test]
+	fn insecure_mode_stays_in_sight() {
+		let config = ConfigFile {
+			insecure: true,
+			..ConfigFile::default()
+		};
+		let mut app = App::new(
+			"https://localhost:1",
+			None,
+			&config,
+			Store::default(),
+		)
+		.unwrap();
+		app.menu_collapsed = true;
+		let lines = screen_lines(|f| render(f, &mut app));
+		assert!(
+			lines[0].starts_with(
+				"Clash (disconnected) (TLS unverified)"
+			),
+			"{}",
+			lines[0]
+		);
+		// reconnecting keeps the setting
+		assert!(app.http.with_url("https://other:1").unwrap().insecure);
+		let secure = App::new(
+			"https://localhost:1",
+			None,
+			&ConfigFile::default(),
+			Store::default(),
+		)
+		.unwrap();
+		assert!(!secure.http.insecure);
+	}
+
+	#[test]
+	fn secret_is_trimmed_or_rejected() {
+		let http = HttpClient::new(
+			"http://127.0.0.1:9",
+			USER_AGENT,
+			Some("s3cret\n"),
+			false,
+			None,
+		);
+		assert_eq!(http.unwrap().token.as_deref(), Some("s3cret"));
+		let http = HttpClient::new(
+			"http://127.0.0.1:9",
+			USER_AGENT,
+			Some("s3c\nret"),
+			false,
+			None,
+		);
+		assert!(http.is_err());
+	}
+
+	#[test]
+	fn invalid_user_agent_is_an_error() {
+		let config = ConfigFile {
+			user_agent: Some(String::from("clashui\n")),
+			..ConfigFile::default()
+		};
+		let app = App::new(
+			"http://127.0.0.1:9",
+			None,
+			&config,
+			Store::default(),
+		);
+		let err = app.err().unwrap();
+		assert!(err.starts_with("Could not set up the HTTP client"));
+	}
+
+	#[test]
+	fn shrinking_keeps_the_cursor_in_view() {
+		let nodes: Vec<_> =
+			(0..30).map(|i| format!("N{:02}", i)).collect();
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(&format!(
+				r#"{{"G": {{"name": "G", "all": {:?}}}}}"#,
+				nodes
+			))),
+			providers_len: 1,
+			proxies_len: nodes.len(),
+			..ProxiesState::default()
+		};
+		for _ in 0..20 {
+			state.next_proxy();
+		}
+		let backend = tui::backend::TestBackend::new(60, 30);
+		let mut terminal = Terminal::new(backend).unwrap();
+		let draw = |terminal: &mut Terminal<_>, state: &mut _| {
+			terminal.draw(|f| {
+				let rect = f.size();
+				render_proxies(
+					f,
+					&Theme::default(),
+					state,
+					&Pane::Proxies,
+					false,
+					rect,
+				)
+			})
+			.unwrap();
+		};
+		draw(&mut terminal, &mut state);
+		assert_eq!(state.list_offset, 0);
+
+		terminal.backend_mut().resize(60, 12);
+		draw(&mut terminal, &mut state);
+		let area = state.list_area;
+		assert_eq!(area.height, 7);
+		assert_eq!(state.list_offset, 14);
+		let buffer = terminal.backend().buffer();
+		let last = area.y + area.height - 1;
+		let row: String = (area.x..area.x + area.width)
+			.map(|x| buffer.get(x, last).symbol.as_str())
+			.collect();
+		assert!(row.contains("N20"), "{}", row);
+		assert_eq!(state.node_at(area.x + 2, last), Some(20));
+	}
+
+	#[test]
+	fn clicks_land_on_the_drawn_row() {
+		let nodes: Vec<_> =
+			(0..20).map(|i| format!("N{:02}", i)).collect();
+		let mut state = ProxiesState {
+			proxies: Some(parse_proxies(&format!(
+				r#"{{"G": {{"name": "G", "all": {:?}}}}}"#,
+				nodes
+			))),
+			providers_len: 1,
+			proxies_len: nodes.len(),
+			..ProxiesState::default()
+		};
+		for _ in 0..9 {
+			state.next_proxy();
+		}
+		let lines = screen_lines(|f| {
+			let rect = f.size();
+			render_proxies(
+				f,
+				&Theme::default(),
+				&mut state,
+				&Pane::Proxies,
+				false,
+				rect,
+			)
+		});
+		let area = state.list_area;
+		for y in area.y..area.y + area.height {
+			let index = state.node_at(area.x + 2, y).unwrap();
+			assert!(lines[y as usize].contains(&nodes[index]));
+		}
+		assert_eq!(state.node_at(area.x, area.y - 1), None);
+
+		let mut app = proxies_app();
+		app.focus = Pane::Menu;
+		let lines = screen_lines(|f| render(f, &mut app));
+		let y = lines.iter().position(|l| l.contains("Logs")).unwrap();
+		let click = |row| MouseEvent {
+			kind: MouseEventKind::Down(MouseButton::Left),
+			column: 3,
+			row,
+			modifiers: event::KeyModifiers::NONE,
+		};
+		process_mouse(click(y as u16), &mut app);
+		assert_eq!(app.route(), Some(&Route::Logs));
+		// section titles lead nowhere
+		process_mouse(click(1), &mut app);
+		assert_eq!(app.route(), Some(&Route::Logs));
+	}
+
+	fn client(url: &str) -> HttpClient {
+		HttpClient::new(
+			url,
+			USER_AGENT,
+			None,
+			false,
+			Some(REQUEST_TIMEOUT),
+		)
+		.unwrap()
+	}
+
+	fn app(url: &str) -> App {
+		App::new(url, None, &ConfigFile::default(), Store::default())
+			.unwrap()
+	}
+
+	fn proxies_app() -> App {
+		let mut app = app("http://127.0.0.1:9");
+		app.proxies_state.proxies = Some(parse_proxies(PROXIES));
+		app.proxies_state.providers_len = 1;
+		app.proxies_state.proxies_len = 2;
+		app.focus = Pane::Proxies;
+		app
+	}
+
+	#[test]
+	fn gg_and_g_jump_to_either_end_of_a_list() {
+		let mut app = proxies_app();
+		let key = |app: &mut App, c| {
+			process_key(KeyCode::Char(c), app);
+		};
+		key(&mut app, 'G');
+		assert_eq!(app.proxies_state.proxy_index, 1);
+		// a single g waits for the second
+		key(&mut app, 'g');
+		assert_eq!(app.proxies_state.proxy_index, 1);
+		key(&mut app, 'g');
+		assert_eq!(app.proxies_state.proxy_index, 0);
+		// another key in between cancels it
+		key(&mut app, 'g');
+		key(&mut app, 'j');
+		key(&mut app, 'g');
+		assert_eq!(app.proxies_state.proxy_index, 1);
+		app.pending = None;
+
+		// the newest log entries are at the bottom
+		app.focus = Pane::Logs;
+		for i in 0..5 {
+			app.logs_state.push(LogEntry {
+				level: String::from("info"),
+				payload: i.to_string(),
+			});
+		}
+		key(&mut app, 'g');
+		key(&mut app, 'g');
+		assert_eq!(app.logs_state.scroll, 4);
+		key(&mut app, 'G');
+		assert_eq!(app.logs_state.scroll, 0);
+
+		// grouping moved from g to b
+		app.focus = Pane::Connections;
+		key(&mut app, 'b');
+		assert!(app.connections_state.grouping == Grouping::Process);
+	}
+
+	#[test]
+	fn pages_move_by_the_lists_height_and_stop_at_the_ends() {
+		let mut app = proxies_app();
+		app.focus = Pane::Rules;
+		let rules: Vec<Rule> = serde_json::from_str(&format!(
+			"[{}]",
+			vec![
+				r#"{"type": "Match", "payload": "", "proxy": "P"}"#;
+				40
+			]
+			.join(", ")
+		))
+		.unwrap();
+		app.rules_state.rules = Some(rules);
+		app.rules_state.rows = 10;
+		let control = |app: &mut App, c| {
+			let key = KeyEvent::new(
+				KeyCode::Char(c),
+				KeyModifiers::CONTROL,
+			);
+			process_key_event(key, app);
+		};
+
+		control(&mut app, 'd');
+		assert_eq!(app.rules_state.offset, 5);
+		process_key(KeyCode::PageDown, &mut app);
+		assert_eq!(app.rules_state.offset, 15);
+		process_key(KeyCode::PageUp, &mut app);
+		control(&mut app, 'u');
+		control(&mut app, 'u');
+		assert_eq!(app.rules_state.offset, 0);
+		for _ in 0..5 {
+			process_key(KeyCode::PageDown, &mut app);
+		}
+		assert_eq!(app.rules_state.offset, 39);
+
+		// the logs page towards the newest at the bottom
+		app.focus = Pane::Logs;
+		for i in 0..30 {
+			app.logs_state.push(LogEntry {
+				level: String::from("info"),
+				payload: i.to_string(),
+			});
+		}
+		app.logs_state.rows = 8;
+		process_key(KeyCode::PageUp, &mut app);
+		assert_eq!(app.logs_state.scroll, 8);
+		control(&mut app, 'd');
+		assert_eq!(app.logs_state.scroll, 4);
+	}
+
+	#[test]
+	fn prompt_keys_do_not_reach_the_pane() {
+		let mut app = proxies_app();
+		app.modal =
+			Some(Modal::Prompt(Prompt::LogFilter, String::new()));
+
+		for c in "jkHLvq".chars() {
+			let res = process_key(KeyCode::Char(c), &mut app);
+			assert!(matches!(res, ProcessResult::Noop));
+		}
+		assert_eq!(app.proxies_state.proxy_index, 0);
+		assert_eq!(app.proxies_state.provider, 0);
+		assert!(app.proxies_state.view == ProxiesView::Grouped);
+		match &app.modal {
+			Some(Modal::Prompt(_, text)) => {
+				assert_eq!(text, "jkHLvq")
+			}
+			_ => panic!("prompt closed"),
+		}
+
+		process_key(KeyCode::Enter, &mut app);
+		assert!(app.modal.is_none());
+		assert_eq!(app.logs_state.filter, "jkHLvq");
+	}
+
+	#[test]
+	fn node_search_filters_as_you_type() {
+		let mut app = proxies_app();
+		let highlighted = |app: &App| {
+			app.proxies_state
+				.highlighted()
+				.map(|(_, n)| n.to_owned())
+		};
+
+		process_key(KeyCode::Char('/'), &mut app);
+		process_key(KeyCode::Char('b'), &mut app);
+		assert_eq!(app.proxies_state.proxies_len, 1);
+		assert_eq!(highlighted(&app).as_deref(), Some("B"));
+		process_key(KeyCode::Esc, &mut app);
+		assert_eq!(app.proxies_state.filter, "");
+		assert_eq!(app.proxies_state.proxies_len, 2);
+
+		// the cursor stays on B while it matches
+		app.proxies_state.next_proxy();
+		for code in [KeyCode::Char('/'), KeyCode::Char('B')] {
+			process_key(code, &mut app);
+		}
+		process_key(KeyCode::Enter, &mut app);
+		process_key(KeyCode::Char('j'), &mut app);
+		assert_eq!(app.proxies_state.proxy_index, 0);
+		assert_eq!(highlighted(&app).as_deref(), Some("B"));
+		assert_eq!(proxies_title(&app.proxies_state), "Proxies \"B\"");
+
+		process_key(KeyCode::Char('/'), &mut app);
+		process_key(KeyCode::Backspace, &mut app);
+		assert_eq!(app.proxies_state.proxies_len, 2);
+		process_key(KeyCode::Esc, &mut app);
+		assert_eq!(app.proxies_state.filter, "B");
+		assert_eq!(app.proxies_state.proxies_len, 1);
+	}
+
+	#[test]
+	fn overlay_keys_do_not_reach_the_pane() {
+		let mut app = proxies_app();
+		app.modal = Some(Modal::Recent(0));
+
+		process_key(KeyCode::Char('j'), &mut app);
+		process_key(KeyCode::Char('L'), &mut app);
+		assert_eq!(app.proxies_state.proxy_index, 0);
+		assert_eq!(app.proxies_state.provider, 0);
+		assert!(app.modal.is_some());
+
+		process_key(KeyCode::Esc, &mut app);
+		assert!(app.modal.is_none());
+		assert!(app.focus == Pane::Proxies);
+
+		process_key(KeyCode::Char('j'), &mut app);
+		assert_eq!(app.proxies_state.proxy_index, 1);
+	}
+
+	#[test]
+	fn mode_cycling_wraps_both_ways() {
+		let mut state = GeneralState::new();
+		let mut seen = Vec::new();
+		for _ in 0..4 {
+			seen.push(Mode::ALL[state.index].clone());
+			state.next_mode();
+		}
+		assert_eq!(
+			seen,
+			[Mode::Global, Mode::Rule, Mode::Direct, Mode::Global]
+		);
+
+		let mut state = GeneralState::new();
+		state.previous_mode();
+		assert_eq!(Mode::ALL[state.index], Mode::Direct);
+		state.previous_mode();
+		state.previous_mode();
+		state.previous_mode();
+		assert_eq!(Mode::ALL[state.index], Mode::Direct);
+	}
+
+	#[test]
+	fn select_mode_sends_the_mode_under_the_cursor() {
+		let patches = Arc::new(Mutex::new(Vec::new()));
+		let sent = patches.clone();
+		let url = mock_server(move |method, path, body| {
+			if method == "PATCH" && path == "/configs" {
+				sent.lock().unwrap().push(body.to_owned());
+			}
+			(200, String::from(r#"{"mode": "direct"}"#))
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.focus = Pane::General;
+		process_key(KeyCode::Char('k'), &mut app);
+		process_key(KeyCode::Char(' '), &mut app);
+		receive(&mut app);
+
+		assert_eq!(*patches.lock().unwrap(), [r#"{"mode":"direct"}"#]);
+		assert!(app.general_state.is_active(&Mode::Direct));
+		assert!(app.status.is_none());
+	}
+
+	#[test]
+	fn settings_patch_only_their_field() {
+		let patches = Arc::new(Mutex::new(Vec::new()));
+		let sent = patches.clone();
+		let url = mock_server(move |method, _, body| {
+			if method == "PATCH" {
+				sent.lock().unwrap().push(body.to_owned());
+			}
+			let config = r#"{"mode": "rule", "allow-lan": false,
+				"log-level": "error"}"#;
+			(200, String::from(config))
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.focus = Pane::General;
+		process_key(KeyCode::Char('a'), &mut app);
+		assert_eq!(status_text(&app), Some("allow-lan is not loaded"));
+		app.fetch_config();
+		receive(&mut app);
+		process_key(KeyCode::Char('a'), &mut app);
+		receive(&mut app);
+		process_key(KeyCode::Char('L'), &mut app);
+		receive(&mut app);
+		assert!(app.status.is_none());
+		assert_eq!(
+			*patches.lock().unwrap(),
+			[r#"{"allow-lan":true}"#, r#"{"log-level":"silent"}"#]
+		);
+	}
+
+	#[test]
+	fn config_reloads_from_the_given_file() {
+		let puts = Arc::new(Mutex::new(Vec::new()));
+		let sent = puts.clone();
+		let url = mock_server(move |method, path, body| {
+			if method == "PUT" {
+				sent.lock()
+					.unwrap()
+					.push(format!("{} {}", path, body));
+				if body.contains("missing") {
+					let body = r#"{"message": "no such file"}"#;
+					return (400, String::from(body));
+				}
+			}
+			(200, String::from(r#"{"mode": "rule"}"#))
+		});
+		let http = client(&url);
+		assert!(runtime().block_on(http.reload_config("")).is_ok());
+		let err = runtime()
+			.block_on(http.reload_config("/etc/missing.yaml"))
+			.unwrap_err();
+		assert_eq!(err.to_string(), "no such file");
+		assert_eq!(
+			*puts.lock().unwrap(),
+			[
+				r#"/configs?force=true {"path":""}"#,
+				r#"/configs?force=true {"path":"/etc/missing.yaml"}"#,
+			]
+		);
+	}
+
+	#[test]
+	fn rejected_mode_stops_being_tried() {
+		let patches = Arc::new(Mutex::new(0));
+		let sent = patches.clone();
+		let url = mock_server(move |method, _, _| {
+			if method == "PATCH" {
+				*sent.lock().unwrap() += 1;
+				let body =
+					r#"{"message": "mode not supported"}"#;
+				return (400, String::from(body));
+			}
+			(200, String::from(r#"{"mode": "rule"}"#))
+		});
+		let _runtime = runtime().enter();
+		let mut app = app(&url);
+		app.focus = Pane::General;
+		process_key(KeyCode::Char(' '), &mut app);
+		receive(&mut app);
+		assert_eq!(
+			status_text(&app),
+			Some("Could not switch to global: mode not supported")
+		);
+		let state = &app.general_state;
+		assert_eq!(state.unavailable(&Mode::Global), None);
+		process_key(KeyCode::Char(' '), &mut app);
+		receive(&mut app);
+		let state = &app.general_state;
+		assert_eq!(
+			state.unavailable(&Mode::Global),
+			Some("mode not supported")
+		);
+
+		process_key(KeyCode::Char(' '), &mut app);
+		assert_eq!(
+			status_text(&app),
+			Some("global is unavailable: mode not supported")
+		);
+		assert_eq!(*patches.lock().unwrap(), MODE_ATTEMPTS);
+		assert_eq!(app.general_state.unavailable(&Mode::Rule), None);
+	}
+
+	#[test]
+	fn active_mode_ignores_case() {
+		let mut state = GeneralState::new();
+		assert!(!state.is_active(&Mode::Rule));
+
+		for mode in ["Rule", "rule", "RULE"] {
+			let body = format!(r#"{{"mode": "{}"}}"#, mode);
+			state.config =
+				Some(serde_json::from_str(&body).unwrap());
+			assert!(state.is_active(&Mode::Rule), "{}", mode);
+			assert!(!state.is_active(&Mode::Global), "{}", mode);
+			assert!(!state.is_active(&Mode::Direct), "{}", mode);
+		}
+	}
+
+	#[test]
+	fn unknown_modes_are_kept_and_shown() {
+		let mut app = proxies_app();
+		app.proxies_state.proxies = None;
+		app.general_state.config = Some(Config {
+			mode: Mode::from(String::from("Script")),
+			allow_lan: None,
+			log_level: None,
+		});
+		assert!(!Mode::ALL
+			.iter()
+			.any(|m| app.general_state.is_active(m)));
+
+		let lines = screen_lines(|f| {
+			render_general(
+				f,
+				&Theme::default(),
+				&mut app.general_state,
+				&app.proxies_state,
+				&app.focus,
+				false,
+				f.size(),
+			)
+		});
+		assert!(lines[0].contains("General (in Script mode)"));
+		assert_eq!(
+			summary(&app.general_state, &app.proxies_state),
+			"mode: Script\n"
+		);
+	}
 }
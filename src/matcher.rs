@@ -0,0 +1,162 @@
+//! A small approximation of Clash's rule matcher.
+//!
+//! Only the host based rule types are understood (DOMAIN, DOMAIN-SUFFIX,
+//! DOMAIN-KEYWORD, IP-CIDR and IP-CIDR6) plus the final MATCH rule. Every
+//! other rule type (GEOIP, PROCESS-NAME, ports, rule sets, ...) is skipped,
+//! and IP rules only apply to IP literals since no DNS resolution is done.
+//! The result is a debugging aid, not a guarantee of what Clash will do.
+
+use crate::Rule;
+use std::net::IpAddr;
+
+pub fn find<'a>(rules: &'a [Rule], host: &str) -> Option<&'a Rule> {
+	let host = host.trim().trim_end_matches('.').to_ascii_lowercase();
+	rules.iter().find(|rule| matches(rule, &host))
+}
+
+fn matches(rule: &Rule, host: &str) -> bool {
+	let payload = rule.payload.to_ascii_lowercase();
+	// The API reports `DomainSuffix` while configs use `DOMAIN-SUFFIX`.
+	let kind: String = rule
+		.kind
+		.chars()
+		.filter(|c| *c != '-' && *c != '_')
+		.map(|c| c.to_ascii_uppercase())
+		.collect();
+
+	match kind.as_str() {
+		"DOMAIN" => host == payload,
+		"DOMAINSUFFIX" => {
+			host == payload
+				|| host.ends_with(&format!(".{}", payload))
+		}
+		"DOMAINKEYWORD" => host.contains(&payload),
+		"IPCIDR" | "IPCIDR6" => match host.parse() {
+			Ok(ip) => cidr_contains(&payload, ip),
+			_ => false,
+		},
+		"MATCH" => true,
+		_ => false,
+	}
+}
+
+fn cidr_contains(cidr: &str, ip: IpAddr) -> bool {
+	let (network, prefix) = match cidr.split_once('/') {
+		Some((network, prefix)) => (network, prefix),
+		_ => (cidr, ""),
+	};
+	let network: IpAddr = match network.parse() {
+		Ok(network) => network,
+		_ => return false,
+	};
+
+	match (network, ip) {
+		(IpAddr::V4(network), IpAddr::V4(ip)) => {
+			let prefix = prefix.parse().unwrap_or(32u32);
+			if prefix > 32 {
+				return false;
+			}
+			let mask =
+				u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+			u32::from(network) & mask == u32::from(ip) & mask
+		}
+		(IpAddr::V6(network), IpAddr::V6(ip)) => {
+			let prefix = prefix.parse().unwrap_or(128u32);
+			if prefix > 128 {
+				return false;
+			}
+			let mask = u128::MAX
+				.checked_shl(128 - prefix)
+				.unwrap_or(0);
+			u128::from(network) & mask == u128::from(ip) & mask
+		}
+		_ => false,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rule(kind: &str, payload: &str, proxy: &str) -> Rule {
+		Rule {
+			kind: kind.to_owned(),
+			payload: payload.to_owned(),
+			proxy: proxy.to_owned(),
+			source: None,
+		}
+	}
+
+	fn rules() -> Vec<Rule> {
+		vec![
+			rule("DomainKeyword", "ads", "REJECT"),
+			rule("Domain", "example.com", "Exact"),
+			rule("DOMAIN-SUFFIX", "google.com", "Proxy"),
+			rule("IPCIDR", "10.0.0.0/8", "DIRECT"),
+			rule("IPCIDR6", "fd00::/8", "DIRECT6"),
+			rule("GeoIP", "CN", "DIRECT"),
+			rule("Match", "", "Final"),
+		]
+	}
+
+	fn proxy_for(host: &str) -> Option<String> {
+		find(&rules(), host).map(|r| r.proxy.clone())
+	}
+
+	#[test]
+	fn domain_requires_exact_host() {
+		assert_eq!(proxy_for("example.com").as_deref(), Some("Exact"));
+		assert_eq!(proxy_for("EXAMPLE.com.").as_deref(), Some("Exact"));
+		assert_eq!(
+			proxy_for("www.example.com").as_deref(),
+			Some("Final")
+		);
+	}
+
+	#[test]
+	fn domain_suffix_matches_subdomains_only_on_label_boundary() {
+		assert_eq!(proxy_for("google.com").as_deref(), Some("Proxy"));
+		assert_eq!(
+			proxy_for("mail.google.com").as_deref(),
+			Some("Proxy")
+		);
+		assert_eq!(
+			proxy_for("notgoogle.com").as_deref(),
+			Some("Final")
+		);
+	}
+
+	#[test]
+	fn first_matching_rule_wins() {
+		assert_eq!(
+			proxy_for("ads.google.com").as_deref(),
+			Some("REJECT")
+		);
+	}
+
+	#[test]
+	fn ip_cidr_matches_literals_only() {
+		assert_eq!(proxy_for("10.1.2.3").as_deref(), Some("DIRECT"));
+		assert_eq!(proxy_for("11.1.2.3").as_deref(), Some("Final"));
+		assert_eq!(proxy_for("fd12::1").as_deref(), Some("DIRECT6"));
+		assert_eq!(
+			proxy_for("10.example.org").as_deref(),
+			Some("Final")
+		);
+	}
+
+	#[test]
+	fn cidr_edge_prefixes() {
+		let ip = "192.168.1.1".parse().unwrap();
+		assert!(cidr_contains("0.0.0.0/0", ip));
+		assert!(cidr_contains("192.168.1.1/32", ip));
+		assert!(cidr_contains("192.168.1.1", ip));
+		assert!(!cidr_contains("192.168.1.0/33", ip));
+		assert!(!cidr_contains("::/0", ip));
+	}
+
+	#[test]
+	fn no_rules_no_match() {
+		assert!(find(&[], "example.com").is_none());
+	}
+}
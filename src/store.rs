@@ -0,0 +1,129 @@
+//! State that survives restarts, kept as JSON next to the config file.
+
+use crate::Route;
+use serde::{Deserialize, Serialize};
+use std::{
+	collections::BTreeMap,
+	env, fs, io,
+	path::{Path, PathBuf},
+};
+
+const RECENT_LEN: usize = 5;
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct Store {
+	#[serde(default)]
+	pub recent: Vec<Recent>,
+	#[serde(default)]
+	pub profiles: Vec<Profile>,
+	// the page open when quitting, by route since the menu can change
+	#[serde(default)]
+	pub last_route: Option<Route>,
+	// the group tab selected on the Proxies page
+	#[serde(default)]
+	pub last_group: Option<String>,
+	// where `save` writes, None keeps everything in memory
+	#[serde(skip)]
+	path: Option<PathBuf>,
+}
+
+#[derive(Clone, PartialEq, Deserialize, Serialize)]
+pub struct Recent {
+	pub group: String,
+	pub node: String,
+}
+
+// the mode and every selector's node, saved under a name
+#[derive(Clone, Deserialize, Serialize)]
+pub struct Profile {
+	pub name: String,
+	pub mode: Option<String>,
+	// group to node
+	#[serde(default)]
+	pub selections: BTreeMap<String, String>,
+}
+
+impl Store {
+	// the file next to the config file
+	pub fn load() -> Self {
+		Self::load_from(path())
+	}
+
+	// a missing or unreadable file just means starting fresh
+	pub fn load_from(path: Option<PathBuf>) -> Self {
+		let mut store: Self = path
+			.as_deref()
+			.and_then(|path| fs::read(path).ok())
+			.and_then(|bytes| serde_json::from_slice(&bytes).ok())
+			.unwrap_or_default();
+		store.path = path;
+		store
+	}
+
+	pub fn save(&self) -> io::Result<()> {
+		let path: &Path = match &self.path {
+			Some(path) => path,
+			_ => return Ok(()),
+		};
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(path, serde_json::to_vec_pretty(self)?)
+	}
+
+	pub fn push_recent(&mut self, group: &str, node: &str) {
+		let recent = Recent {
+			group: group.to_owned(),
+			node: node.to_owned(),
+		};
+		self.recent.retain(|r| r != &recent);
+		self.recent.insert(0, recent);
+		self.recent.truncate(RECENT_LEN);
+	}
+
+	// replaces a profile of the same name
+	pub fn save_profile(&mut self, profile: Profile) {
+		match self.profiles.iter_mut().find(|p| p.name == profile.name)
+		{
+			Some(existing) => *existing = profile,
+			_ => self.profiles.push(profile),
+		}
+	}
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+	let base = match env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+		_ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+	};
+	Some(base.join("clashui"))
+}
+
+fn path() -> Option<PathBuf> {
+	config_dir().map(|dir| dir.join("state.json"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn saves_only_to_the_path_it_was_loaded_from() {
+		let dir = env::temp_dir()
+			.join(format!("clashui-store-{}", std::process::id()));
+		let path = dir.join("state.json");
+		let mut store = Store::load_from(Some(path.clone()));
+		assert!(store.recent.is_empty());
+		store.push_recent("Proxy", "JP-01");
+		store.save().unwrap();
+
+		let store = Store::load_from(Some(path));
+		assert_eq!(store.recent[0].node, "JP-01");
+		fs::remove_dir_all(dir).unwrap();
+
+		// without a path nothing is written
+		let mut store = Store::default();
+		store.push_recent("Proxy", "JP-01");
+		assert!(store.save().is_ok());
+	}
+}
@@ -0,0 +1,147 @@
+//! Colors for the parts of the UI that mark the cursor, what is in use and
+//! what has focus, set from the `[theme]` table of the config file.
+//!
+//! ```toml
+//! [theme]
+//! highlight = "#d0d0ff"
+//! active = "red"
+//! selection = "blue"
+//! border = "darkgray"
+//! ```
+//!
+//! Colors are the terminal's named ones (`lightblue`, `dark-gray`, ...), an
+//! index into its 256 color palette, or `#rrggbb`.
+
+use serde::{de, Deserialize, Deserializer};
+use tui::{
+	style::{Color, Style},
+	widgets::{Block, Borders},
+};
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+	// background of the row under the cursor
+	#[serde(deserialize_with = "color")]
+	pub highlight: Color,
+	// the node, mode or rule in use
+	#[serde(deserialize_with = "color")]
+	pub active: Color,
+	// the selected tab and the border of the focused pane
+	#[serde(deserialize_with = "color")]
+	pub selection: Color,
+	#[serde(deserialize_with = "color")]
+	pub border: Color,
+}
+
+impl Default for Theme {
+	fn default() -> Self {
+		Self {
+			highlight: Color::LightBlue,
+			active: Color::LightRed,
+			selection: Color::LightBlue,
+			border: Color::Reset,
+		}
+	}
+}
+
+impl Theme {
+	// the bordered block every pane and popup is drawn in
+	pub fn block(&self) -> Block<'static> {
+		Block::default()
+			.borders(Borders::ALL)
+			.border_style(Style::default().fg(self.border))
+	}
+}
+
+fn color<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Color, D::Error> {
+	let text = String::deserialize(deserializer)?;
+	parse_color(&text).ok_or_else(|| {
+		de::Error::custom(format!(
+			"unknown color `{}`, expected a name like `lightblue`, \
+			 a number up to 255 or `#rrggbb`",
+			text
+		))
+	})
+}
+
+pub fn parse_color(text: &str) -> Option<Color> {
+	let text = text.trim();
+	if let Some(hex) = text.strip_prefix('#') {
+		if hex.len() != 6 || !hex.is_ascii() {
+			return None;
+		}
+		let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+		return Some(Color::Rgb(
+			channel(0).ok()?,
+			channel(2).ok()?,
+			channel(4).ok()?,
+		));
+	}
+	if let Ok(index) = text.parse() {
+		return Some(Color::Indexed(index));
+	}
+
+	let name: String = text
+		.chars()
+		.filter(|c| !matches!(c, '-' | '_' | ' '))
+		.map(|c| c.to_ascii_lowercase())
+		.collect();
+	let color = match name.as_str() {
+		"reset" | "default" => Color::Reset,
+		"black" => Color::Black,
+		"red" => Color::Red,
+		"green" => Color::Green,
+		"yellow" => Color::Yellow,
+		"blue" => Color::Blue,
+		"magenta" => Color::Magenta,
+		"cyan" => Color::Cyan,
+		"gray" | "grey" => Color::Gray,
+		"darkgray" | "darkgrey" => Color::DarkGray,
+		"lightred" => Color::LightRed,
+		"lightgreen" => Color::LightGreen,
+		"lightyellow" => Color::LightYellow,
+		"lightblue" => Color::LightBlue,
+		"lightmagenta" => Color::LightMagenta,
+		"lightcyan" => Color::LightCyan,
+		"white" => Color::White,
+		_ => return None,
+	};
+	Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_names_indexes_and_hex() {
+		assert_eq!(parse_color("LightBlue"), Some(Color::LightBlue));
+		assert_eq!(parse_color("dark-gray"), Some(Color::DarkGray));
+		assert_eq!(parse_color("light_red"), Some(Color::LightRed));
+		assert_eq!(parse_color("236"), Some(Color::Indexed(236)));
+		assert_eq!(
+			parse_color("#FF8000"),
+			Some(Color::Rgb(255, 128, 0))
+		);
+		assert_eq!(parse_color("#ff80"), None);
+		assert_eq!(parse_color("#ff80zz"), None);
+		assert_eq!(parse_color("256"), None);
+		assert_eq!(parse_color("orange"), None);
+	}
+
+	#[test]
+	fn unset_colors_keep_their_defaults() {
+		let theme: Theme =
+			toml::from_str("highlight = \"#202020\"").unwrap();
+		assert_eq!(theme.highlight, Color::Rgb(32, 32, 32));
+		assert_eq!(theme.active, Theme::default().active);
+
+		let err = toml::from_str::<Theme>("border = \"orange\"")
+			.err()
+			.unwrap();
+		assert!(err.to_string().contains("unknown color `orange`"));
+	}
+}